@@ -0,0 +1,330 @@
+//! Images used by the book
+//!
+//! Recipes and introduction pages show images with `![alt](url)`, and a recipe can
+//! have a picture in the `image` field of its header. Their paths are relative to the
+//! file using them, so `![](photos/ragu.jpg)` in `recipes/ragu.md` is the image
+//! `recipes/photos/ragu.jpg` of the book. Urls with a scheme, like `https://`, are not
+//! files of the book and are left alone.
+
+use std::{
+    collections::BTreeMap,
+    fs::{copy as copy_file, create_dir_all},
+    iter::once,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use markdown::mdast::{Image, Node};
+
+use super::{recipe::Recipe, Book};
+use crate::{diagnostic::Errors, parsers::markdown::Markdown};
+
+/// Directory of the recipe files, that their images are relative to
+const RECIPES: &str = "recipes";
+/// Directory of the introduction pages, that their images are relative to
+const INTRODUCTION: &str = "introduction";
+
+/// The image at `url`, used by a file in the directory `dir` of the book, as a path
+/// relative to the book
+///
+/// Urls with a scheme or that are only an anchor give `None`.
+pub fn resolve(dir: &str, url: &str) -> anyhow::Result<Option<PathBuf>> {
+    if url.contains(':') || url.starts_with('#') {
+        return Ok(None);
+    }
+    if url.trim().is_empty() {
+        bail!("The path of the image is empty")
+    }
+    let mut path = PathBuf::from(dir);
+    for component in Path::new(url).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(name) => path.push(name),
+            Component::ParentDir => {
+                if !path.pop() {
+                    bail!("`{url}` is outside of the book")
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("`{url}` is an absolute path, it must be relative to the file using it")
+            }
+        }
+    }
+    Ok(Some(path))
+}
+
+/// The picture in the header of the recipe, as a path relative to the book
+#[must_use]
+pub fn recipe_image(recipe: &Recipe) -> Option<PathBuf> {
+    let image = recipe.image.as_ref()?;
+    resolve(RECIPES, &image.to_string_lossy()).ok().flatten()
+}
+
+/// The urls of the images in some markdown
+#[must_use]
+pub fn urls(md: &Markdown) -> Vec<&str> {
+    fn visit<'n>(node: &'n Node, urls: &mut Vec<&'n str>) {
+        match node {
+            Node::Image(Image { url, .. }) => urls.push(url),
+            node => {
+                for child in node.children().into_iter().flatten() {
+                    visit(child, urls)
+                }
+            }
+        }
+    }
+    let mut urls = vec![];
+    visit(&md.0, &mut urls);
+    urls
+}
+
+/// An image as written in the book
+struct Use {
+    /// The part of the book using the image, like ``recipe `ragu` ``
+    user: String,
+    /// Directory of the file using the image
+    dir: &'static str,
+    url: String,
+}
+
+fn uses(book: &Book) -> Vec<Use> {
+    let mut uses = vec![];
+    let recipes = book
+        .recipes
+        .iter()
+        .map(|(name, recipe)| (format!("recipe `{name}`"), recipe))
+        .chain(
+            book.translations
+                .iter()
+                .flat_map(|(language, translation)| {
+                    translation.recipes.iter().map(move |(name, recipe)| {
+                        (format!("recipe `{name}` in `{language}`"), recipe)
+                    })
+                }),
+        );
+    for (user, recipe) in recipes {
+        if let Some(image) = &recipe.image {
+            uses.push(Use {
+                user: user.clone(),
+                dir: RECIPES,
+                url: image.to_string_lossy().into_owned(),
+            })
+        }
+        for url in recipe.markdowns().into_iter().flat_map(urls) {
+            uses.push(Use {
+                user: user.clone(),
+                dir: RECIPES,
+                url: url.to_owned(),
+            })
+        }
+    }
+    let introductions = once(("the introduction".to_owned(), book.introduction.markdowns())).chain(
+        book.translations.iter().map(|(language, translation)| {
+            (
+                format!("the introduction in `{language}`"),
+                translation.introduction.markdowns(),
+            )
+        }),
+    );
+    for (user, mds) in introductions {
+        for url in mds.into_iter().flat_map(urls) {
+            uses.push(Use {
+                user: user.clone(),
+                dir: INTRODUCTION,
+                url: url.to_owned(),
+            })
+        }
+    }
+    uses
+}
+
+/// The images that are files of the book, as paths relative to it, with the parts of
+/// the book using them
+pub fn images(book: &Book) -> anyhow::Result<BTreeMap<PathBuf, Vec<String>>> {
+    let mut errors = Errors::default();
+    let mut images: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for Use { user, dir, url } in uses(book) {
+        match resolve(dir, &url) {
+            Ok(Some(path)) => {
+                let users = images.entry(path).or_default();
+                if !users.contains(&user) {
+                    users.push(user)
+                }
+            }
+            Ok(None) => {}
+            Err(err) => errors.push(err.context(format!("Invalid image in {user}"))),
+        }
+    }
+    errors.into_result(images)
+}
+
+/// Check that the images used by the book exist
+///
+/// A book that was not loaded from a directory has no files to check.
+pub fn check(book: &Book) -> anyhow::Result<()> {
+    let images = images(book)?;
+    let Some(root) = &book.root else {
+        log::debug!("The book was not loaded from a directory, its images are not checked");
+        return Ok(());
+    };
+    let mut errors = Errors::default();
+    for (path, users) in images {
+        if !root.join(&path).is_file() {
+            errors.push(anyhow!(
+                "The image `{}` does not exist, it is used by {}",
+                path.display(),
+                users.join(", ")
+            ))
+        }
+    }
+    errors.into_result(())
+}
+
+/// Copy the images of the book into the source directory `output`, at the same paths
+pub fn copy(book: &Book, output: &Path) -> anyhow::Result<()> {
+    let images = images(book)?;
+    if images.is_empty() {
+        return Ok(());
+    }
+    let Some(root) = &book.root else {
+        log::warn!("The book was not loaded from a directory, its images are not copied");
+        return Ok(());
+    };
+    if root.canonicalize().ok() == output.canonicalize().ok() {
+        return Ok(());
+    }
+    for path in images.keys() {
+        log::debug!("Copying image {}", path.display());
+        let target = output.join(path);
+        if let Some(dir) = target.parent() {
+            create_dir_all(dir).context("Cannot create images directory")?;
+        }
+        copy_file(root.join(path), target)
+            .with_context(|| format!("Cannot copy image `{}`", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Point the images in the markdown texts to `url(path)`, where `path` is the image
+/// relative to the book
+///
+/// Images with a scheme or with an invalid path are left as they are.
+pub fn rewrite(book: &mut Book, url: impl Fn(&Path) -> String) {
+    let mut mds: Vec<_> = book
+        .introduction
+        .markdowns_mut()
+        .into_iter()
+        .map(|md| (INTRODUCTION, md))
+        .collect();
+    for recipe in book.recipes.values_mut() {
+        mds.extend(recipe.markdowns_mut().into_iter().map(|md| (RECIPES, md)))
+    }
+    for translation in book.translations.values_mut() {
+        mds.extend(
+            translation
+                .introduction
+                .markdowns_mut()
+                .into_iter()
+                .map(|md| (INTRODUCTION, md)),
+        );
+        for recipe in translation.recipes.values_mut() {
+            mds.extend(recipe.markdowns_mut().into_iter().map(|md| (RECIPES, md)))
+        }
+    }
+
+    fn visit(node: &mut Node, dir: &str, url: &impl Fn(&Path) -> String) {
+        match node {
+            Node::Image(Image { url: image, .. }) => {
+                if let Ok(Some(path)) = resolve(dir, image) {
+                    *image = url(&path)
+                }
+            }
+            node => {
+                for child in node.children_mut().into_iter().flatten() {
+                    visit(child, dir, url)
+                }
+            }
+        }
+    }
+    for (dir, md) in mds {
+        visit(&mut md.0, dir, &url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe, TempBook};
+
+    fn md(source: &str) -> Markdown {
+        serde_yaml::from_str(&format!("{source:?}")).unwrap()
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_file() {
+        let resolved = |url| resolve(RECIPES, url).map_err(|err| err.to_string());
+        assert_eq!(
+            resolved("foto/sugo.jpg"),
+            Ok(Some(PathBuf::from("recipes/foto/sugo.jpg")))
+        );
+        assert_eq!(
+            resolved("./../foto/sugo.jpg"),
+            Ok(Some(PathBuf::from("foto/sugo.jpg")))
+        );
+        assert_eq!(resolved("https://example.com/sugo.jpg"), Ok(None));
+        assert_eq!(resolved("#sugo"), Ok(None));
+        assert_eq!(
+            resolved("../../sugo.jpg"),
+            Err("`../../sugo.jpg` is outside of the book".to_owned())
+        );
+        assert!(resolved("/sugo.jpg").unwrap_err().contains("absolute path"));
+        assert!(resolved(" ").is_err());
+    }
+
+    /// A book with a recipe showing `sugo.jpg` twice, and `pesto.jpg` in its description
+    fn book_with_images() -> Book {
+        let mut sugo = recipe("time: 10m\ningredients: [Sale]\nimage: foto/sugo.jpg");
+        sugo.descr = md("![Il sugo](foto/sugo.jpg) e ![il pesto](../foto/pesto.jpg)");
+        book([("sugo", sugo)])
+    }
+
+    #[test]
+    fn missing_images_are_reported_with_their_users() {
+        let mut book = book_with_images();
+        let images = images(&book).unwrap();
+        assert_eq!(
+            images.keys().collect::<Vec<_>>(),
+            [
+                Path::new("foto/pesto.jpg"),
+                Path::new("recipes/foto/sugo.jpg")
+            ]
+        );
+        assert_eq!(
+            images[Path::new("recipes/foto/sugo.jpg")],
+            ["recipe `sugo`"]
+        );
+
+        let sources = TempBook::new(&book, "missing-images");
+        create_dir_all(sources.0.join("recipes/foto")).unwrap();
+        std::fs::write(sources.0.join("recipes/foto/sugo.jpg"), "").unwrap();
+        book.root = Some(sources.0.clone());
+        let errors = check(&book).unwrap_err().downcast::<Errors>().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors.0[0].to_string(),
+            "The image `foto/pesto.jpg` does not exist, it is used by recipe `sugo`"
+        );
+    }
+
+    #[test]
+    fn images_are_pointed_to_the_output() {
+        let mut book = book_with_images();
+        book.recipes.get_mut("sugo").unwrap().descr =
+            md("![Il sugo](foto/sugo.jpg) e ![il pesto](https://example.com/pesto.jpg)");
+        rewrite(&mut book, |path| format!("/img/{}", path.display()));
+        assert_eq!(
+            serde_json::to_value(&book.recipes["sugo"].descr).unwrap(),
+            "![Il sugo](/img/recipes/foto/sugo.jpg) e ![il pesto](https://example.com/pesto.jpg)"
+        );
+    }
+}
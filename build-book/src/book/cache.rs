@@ -0,0 +1,308 @@
+//! Persistent cache of the builds, to skip the work on unchanged recipes
+//!
+//! The parsed recipes are reused while the modification time of their source stays
+//! the same, together with what was ignored while parsing them, so the lints still
+//! report it. The frontends record the files they write with a stamp of what they
+//! were generated from, so unchanged files are not generated again, and the files
+//! of the previous build that are not produced anymore can be removed.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    env::temp_dir,
+    fs::{create_dir_all, read_to_string, remove_file, write},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{language::Language, recipe::Recipe};
+
+/// Name of the cache file inside the cache directory
+const CACHE_FILE: &str = "build-book.json";
+
+/// The cache directory of the book at `book`, in the temporary directory of the system
+/// so nothing is written among the sources
+#[must_use]
+pub fn default_dir(book: &Path) -> PathBuf {
+    let book = book.canonicalize().unwrap_or_else(|_| book.to_owned());
+    let name = book
+        .file_name()
+        .map_or_else(|| "book".into(), |name| name.to_string_lossy());
+    let mut hasher = DefaultHasher::new();
+    book.hash(&mut hasher);
+    temp_dir()
+        .join("build-book")
+        .join(format!("{name}-{:016x}", hasher.finish()))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// Where the cache is saved. An in memory cache starts empty and is never saved
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+    /// Version of the program that wrote the cache, as the formats can change
+    version: String,
+    /// Parsed recipes, by name
+    recipes: BTreeMap<String, CachedRecipe>,
+    /// Language the recipes were parsed in, as it decides the accepted headings
+    #[serde(default)]
+    language: Language,
+    /// Files written by the frontends, by output directory, with their stamps
+    outputs: BTreeMap<PathBuf, BTreeMap<PathBuf, u64>>,
+    /// Files produced by this build, by output directory
+    #[serde(skip)]
+    produced: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+impl BuildCache {
+    /// Open the cache saved in `dir`, starting from an empty one if it is missing or
+    /// was written by another version
+    pub fn open(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_owned();
+        let file = dir.join(CACHE_FILE);
+        let cache = read_to_string(&file)
+            .context("Cannot read cache")
+            .and_then(|source| serde_json::from_str::<Self>(&source).context("Cannot parse cache"));
+        match cache {
+            Ok(cache) if cache.version == env!("CARGO_PKG_VERSION") => {
+                log::debug!("Using build cache at {}", file.display());
+                Self {
+                    dir: Some(dir),
+                    ..cache
+                }
+            }
+            Ok(_) => {
+                log::info!("Build cache is from another version, rebuilding everything");
+                Self::empty(Some(dir))
+            }
+            Err(err) => {
+                log::debug!("No usable build cache at {}: {err:#}", file.display());
+                Self::empty(Some(dir))
+            }
+        }
+    }
+
+    /// A cache that is not saved, so everything is always built
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self::empty(None)
+    }
+
+    fn empty(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        log::debug!("Saving build cache to {}", dir.display());
+        create_dir_all(dir).context("Cannot create cache dir")?;
+        write(
+            dir.join(CACHE_FILE),
+            serde_json::to_string(self).context("While serializing cache")?,
+        )
+        .context("Cannot write cache")
+    }
+
+    /// The parsed recipe `name`, if its source was not modified since it was cached
+    /// and the book language is the same
+    #[must_use]
+    pub fn recipe(
+        &self,
+        name: &str,
+        modified: DateTime<Utc>,
+        language: Language,
+    ) -> Option<Recipe> {
+        if language != self.language {
+            return None;
+        }
+        self.recipes
+            .get(name)
+            .filter(|cached| cached.recipe.modified == Some(modified))
+            .map(|cached| {
+                let mut recipe = Recipe {
+                    unknown_keys: cached.unknown_keys.clone(),
+                    unknown_headings: cached.unknown_headings.clone(),
+                    ..cached.recipe.clone()
+                };
+                if cached.total_given {
+                    recipe.time.give_total()
+                }
+                recipe
+            })
+    }
+
+    /// Replace the cached recipes with the ones just loaded
+    pub fn set_recipes(&mut self, recipes: &BTreeMap<String, Recipe>, language: Language) {
+        self.recipes = recipes
+            .iter()
+            .map(|(name, recipe)| {
+                (
+                    name.clone(),
+                    CachedRecipe {
+                        recipe: recipe.clone(),
+                        unknown_keys: recipe.unknown_keys.clone(),
+                        unknown_headings: recipe.unknown_headings.clone(),
+                        total_given: recipe.time.gives_sum_as_total(),
+                    },
+                )
+            })
+            .collect();
+        self.language = language;
+    }
+
+    /// Check if `file` in the `output` directory was already generated from the same
+    /// content, recording it as produced by this build
+    pub fn is_fresh(&mut self, output: &Path, file: &Path, stamp: u64) -> bool {
+        self.produced
+            .entry(output.to_owned())
+            .or_default()
+            .insert(file.to_owned());
+        self.outputs
+            .get(output)
+            .and_then(|files| files.get(file))
+            .is_some_and(|old| *old == stamp)
+    }
+
+    /// Record that `file` in the `output` directory was generated from the content
+    /// with the given stamp
+    pub fn record(&mut self, output: &Path, file: &Path, stamp: u64) {
+        self.produced
+            .entry(output.to_owned())
+            .or_default()
+            .insert(file.to_owned());
+        self.outputs
+            .entry(output.to_owned())
+            .or_default()
+            .insert(file.to_owned(), stamp);
+    }
+
+    /// Remove the files that a previous build wrote in `output`, but this one did not
+    /// produce, like the pages of deleted recipes
+    pub fn remove_stale(&mut self, output: &Path) -> anyhow::Result<()> {
+        let produced = self.produced.remove(output).unwrap_or_default();
+        let Some(files) = self.outputs.get_mut(output) else {
+            return Ok(());
+        };
+        for file in files.keys().filter(|file| !produced.contains(*file)) {
+            log::info!("Removing stale output {}", output.join(file).display());
+            match remove_file(output.join(file)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(err)
+                        .with_context(|| format!("Cannot remove {}", output.join(file).display()))
+                }
+                _ => (),
+            }
+        }
+        files.retain(|file, _| produced.contains(file));
+        Ok(())
+    }
+}
+
+/// A parsed recipe, with the parts of the source that the book model does not keep
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedRecipe {
+    recipe: Recipe,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unknown_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unknown_headings: Vec<String>,
+    /// The total time is given even if it is the sum of the others
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    total_given: bool,
+}
+
+/// Stamp of the content a file is generated from
+pub fn stamp(content: &impl Serialize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // serializing to json cannot fail for the book types
+    hasher.write(&serde_json::to_vec(content).unwrap_or_default());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+
+    use chrono::Duration;
+
+    use super::*;
+    use crate::book::tests::recipe;
+
+    fn cached(modified: DateTime<Utc>) -> BuildCache {
+        let mut sugo = recipe("time: 10m\ningredients: [Sale]");
+        sugo.modified = Some(modified);
+        sugo.unknown_keys = vec!["calorie".to_owned()];
+        let mut cache = BuildCache::disabled();
+        cache.set_recipes(&[("sugo".to_owned(), sugo)].into(), Language::Italian);
+        cache
+    }
+
+    #[test]
+    fn recipes_are_reused_while_unchanged() {
+        let modified = Utc::now();
+        let cache = cached(modified);
+        let sugo = cache.recipe("sugo", modified, Language::Italian).unwrap();
+        assert_eq!(sugo.unknown_keys, ["calorie"]);
+
+        let later = modified + Duration::seconds(1);
+        assert!(cache.recipe("sugo", later, Language::Italian).is_none());
+        assert!(cache.recipe("sugo", modified, Language::English).is_none());
+        assert!(cache.recipe("pesto", modified, Language::Italian).is_none());
+    }
+
+    #[test]
+    fn caches_of_other_versions_are_dropped() {
+        let dir = temp_dir().join(format!("build-book-cache-{}", std::process::id()));
+        let modified = Utc::now();
+        let cache = BuildCache {
+            dir: Some(dir.clone()),
+            ..cached(modified)
+        };
+        cache.save().unwrap();
+        assert!(BuildCache::open(&dir)
+            .recipe("sugo", modified, Language::Italian)
+            .is_some());
+
+        let file = dir.join(CACHE_FILE);
+        let source = read_to_string(&file).unwrap().replace(
+            &format!(r#""version":"{}""#, env!("CARGO_PKG_VERSION")),
+            r#""version":"0.0.0""#,
+        );
+        write(&file, source).unwrap();
+        let reopened = BuildCache::open(&dir);
+        let _ = remove_dir_all(&dir);
+        assert!(reopened
+            .recipe("sugo", modified, Language::Italian)
+            .is_none());
+    }
+
+    #[test]
+    fn outputs_are_fresh_with_the_same_stamp() {
+        let output = temp_dir().join(format!("build-book-outputs-{}", std::process::id()));
+        create_dir_all(&output).unwrap();
+        let (page, old) = (Path::new("sugo.html"), Path::new("pesto.html"));
+        write(output.join(old), "").unwrap();
+
+        let mut cache = BuildCache::disabled();
+        cache.record(&output, page, stamp(&"sugo"));
+        cache.record(&output, old, stamp(&"pesto"));
+        cache.remove_stale(&output).unwrap();
+
+        // the next build produces only the page, with the same content
+        assert!(cache.is_fresh(&output, page, stamp(&"sugo")));
+        assert!(!cache.is_fresh(&output, page, stamp(&"sugo di pomodoro")));
+        cache.remove_stale(&output).unwrap();
+        let removed = !output.join(old).exists();
+        let _ = remove_dir_all(&output);
+        assert!(removed);
+        assert!(!cache.is_fresh(&output, old, stamp(&"pesto")));
+    }
+}
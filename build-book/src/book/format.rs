@@ -0,0 +1,311 @@
+//! Canonical layout of the book source files
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{read_dir, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use markdown::mdast::{Heading, Node, Yaml};
+
+use super::{
+    front_matter::FrontMatter, introduction::Metas, language::Language, recipe::Recipe, tags::Tags,
+    translation,
+};
+use crate::{
+    diagnostic::{Errors, InFile},
+    parsers::{
+        headed_md::HeadedMarkdown,
+        markdown::{parse_headed, stringify, Markdown},
+        title_separated_list::TitleSeparatedList,
+        DisplayMd, FromMd,
+    },
+};
+
+/// Rewrite all the source files of the book at `path` in the canonical layout
+///
+/// Returns the files that were not already formatted. If `check` is set, they are
+/// only reported and left untouched.
+pub fn format_book(path: impl AsRef<Path>, check: bool) -> anyhow::Result<Vec<PathBuf>> {
+    log::info!("Formatting book in {}", path.as_ref().display());
+    let path = path.as_ref();
+
+    let mut files = vec![(path.join("front_matter.yml"), Kind::FrontMatter)];
+    if path.join("tags.yml").exists() {
+        files.push((path.join("tags.yml"), Kind::Tags));
+    }
+    // the headings of the recipes depend on the language of the book
+    let language = FrontMatter::load(path.join("front_matter.yml"))?.language;
+    let introduction = path.join("introduction");
+    let pages = [
+        ("zen", Kind::Sections),
+        ("prologue", Kind::Page),
+        ("warnings", Kind::Sections),
+        ("thanks", Kind::Page),
+    ];
+    files.extend(pages.map(|(name, kind)| (introduction.join(format!("{name}.md")), kind)));
+    for other in Language::ALL.into_iter().filter(|l| *l != language) {
+        files.extend(
+            pages
+                .map(|(name, kind)| {
+                    let file = format!("{name}.{}.md", other.code());
+                    (introduction.join(file), kind)
+                })
+                .into_iter()
+                .filter(|(file, _)| file.exists()),
+        );
+    }
+    let recipes = path.join("recipes");
+    let mut recipes: Vec<PathBuf> = read_dir(&recipes)
+        .and_then(|entries| entries.map(|entry| entry.map(|e| e.path())).try_collect())
+        .context("Cannot read dir")
+        .in_file(&recipes, None)?;
+    recipes.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "md"));
+    recipes.sort();
+    files.extend(recipes.into_iter().map(|path| {
+        // translations are written with the headings of their language
+        let language = path
+            .file_stem()
+            .and_then(|stem| translation::split_name(stem.to_str()?).1)
+            .unwrap_or(language);
+        (path, Kind::Recipe(language))
+    }));
+
+    let mut errors = Errors::default();
+    let mut changed = vec![];
+    for (path, kind) in files {
+        if errors.collect(format_file(&path, kind, check)) == Some(true) {
+            changed.push(path)
+        }
+    }
+    errors.into_result(changed)
+}
+
+/// Format a single file, returning if it was changed
+fn format_file(path: &Path, kind: Kind, check: bool) -> anyhow::Result<bool> {
+    log::debug!("Formatting {}", path.display());
+    let source = read_to_string(path)
+        .context("Cannot read file")
+        .in_file(path, None)?;
+    let formatted = kind
+        .format(&source)
+        .and_then(|formatted| {
+            check_lossless(kind, &source, &formatted)?;
+            Ok(formatted)
+        })
+        .in_file(path, Some(&source))?;
+
+    if formatted == source {
+        return Ok(false);
+    }
+    if check {
+        log::warn!("{} is not formatted", path.display());
+    } else {
+        log::info!("Formatting {}", path.display());
+        write(path, formatted)
+            .context("Cannot write file")
+            .in_file(path, None)?;
+    }
+    Ok(true)
+}
+
+/// The kinds of source files in a book
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    FrontMatter,
+    Tags,
+    /// Introduction page split by titles
+    Sections,
+    /// Introduction page of free markdown
+    Page,
+    /// Recipe, with the section headings of the book language
+    Recipe(Language),
+}
+impl Kind {
+    fn format(self, source: &str) -> anyhow::Result<String> {
+        match self {
+            Kind::FrontMatter => {
+                let front_matter: FrontMatter =
+                    serde_yaml::from_str(source).context("Cannot parse file")?;
+                serde_yaml::to_string(&front_matter).context("While formatting")
+            }
+            Kind::Tags => {
+                let tags: Tags = serde_yaml::from_str(source).context("Cannot parse file")?;
+                serde_yaml::to_string(&tags).context("While formatting")
+            }
+            Kind::Sections => {
+                format_md::<HeadedMarkdown<Metas, TitleSeparatedList<Markdown, Markdown>>>(source)
+            }
+            Kind::Page => format_md::<HeadedMarkdown<Metas, Markdown>>(source),
+            Kind::Recipe(language) => {
+                let recipe =
+                    Recipe::from_md(parse_headed(source), language).context("While parsing")?;
+                let md = recipe.to_md(language).context("While formatting")?;
+                Ok(stringify(&md) + "\n")
+            }
+        }
+    }
+}
+
+fn format_md<T: FromMd + DisplayMd>(source: &str) -> anyhow::Result<String> {
+    let parsed = T::parse(parse_headed(source)).context("While parsing")?;
+    let md = parsed.fmt().context("While formatting")?;
+    Ok(stringify(&md) + "\n")
+}
+
+/// Check that formatting did not drop anything from the source
+///
+/// The parsers skip unknown header keys and the sections under unrecognized headings,
+/// so those would silently disappear once the file is written back.
+fn check_lossless(kind: Kind, source: &str, formatted: &str) -> anyhow::Result<()> {
+    let (source_header, source_words) = contents(kind, source);
+    let (formatted_header, formatted_words) = contents(kind, formatted);
+
+    let dropped_keys: Vec<_> = keys(&source_header)
+        .difference(&keys(&formatted_header))
+        .map(|key| format!("`{key}`"))
+        .collect();
+    if !dropped_keys.is_empty() {
+        bail!(
+            "Formatting would drop the keys {}, remove them or fix their names",
+            dropped_keys.join(", ")
+        )
+    }
+
+    let mut count = BTreeMap::<_, isize>::new();
+    for word in &source_words {
+        *count.entry(word).or_default() += 1;
+    }
+    for word in &formatted_words {
+        *count.entry(word).or_default() -= 1;
+    }
+    let dropped_words: Vec<_> = count
+        .into_iter()
+        .filter(|(_, n)| *n > 0)
+        .map(|(word, _)| word.as_str())
+        .collect();
+    if !dropped_words.is_empty() {
+        bail!(
+            "Formatting would drop some text ({}), probably under an unrecognized heading",
+            dropped_words.join(" ")
+        )
+    }
+    Ok(())
+}
+
+/// The yaml header and the words of the body of a source file
+fn contents(kind: Kind, source: &str) -> (String, Vec<String>) {
+    if let Kind::FrontMatter | Kind::Tags = kind {
+        return (source.to_owned(), vec![]);
+    }
+    let mut header = String::new();
+    let mut words = vec![];
+    for node in parse_headed(source).children().into_iter().flatten() {
+        match node {
+            Node::Yaml(Yaml { value, .. }) => header = value.clone(),
+            // the section headings are rewritten in their canonical form
+            node if is_section_heading(kind, node) => (),
+            node => collect_words(node, &mut words),
+        }
+    }
+    (header, words)
+}
+
+/// Whether `node` is the heading of a recipe section, that can be written with another alias
+fn is_section_heading(kind: Kind, node: &Node) -> bool {
+    match (kind, node) {
+        (Kind::Recipe(language), Node::Heading(Heading { depth: 1, .. })) => {
+            language.section(&node.to_string()).is_some()
+        }
+        _ => false,
+    }
+}
+
+fn collect_words(node: &Node, words: &mut Vec<String>) {
+    match node.children() {
+        Some(children) => {
+            for child in children {
+                collect_words(child, words)
+            }
+        }
+        None => words.extend(node.to_string().split_whitespace().map(ToOwned::to_owned)),
+    }
+}
+
+/// Keys of the headers that are written back with another name
+pub const KEY_ALIASES: &[(&str, &str)] = &[("total", "time"), ("serves", "servings")];
+
+/// The top level keys of a yaml mapping, with the aliases replaced by their key
+fn keys(yaml: &str) -> BTreeSet<String> {
+    let canonical = |key: &str| {
+        KEY_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == key)
+            .map_or(key, |(_, key)| key)
+            .to_owned()
+    };
+    match serde_yaml::from_str(yaml) {
+        Ok(serde_yaml::Value::Mapping(mapping)) => mapping
+            .keys()
+            .filter_map(|key| key.as_str().map(canonical))
+            .collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, TempBook};
+
+    const RECIPE: &str = "---\nname: Sugo\ntotal: 30m\nserves: 2\ningredients: [Pomodoro 200g, Sale]\ntools: [Pentola]\ntags: []\n---\nUn sugo.\n\n# Procedimento\n\n1.  Cuocere il *pomodoro*.\n2. Salare.\n";
+
+    fn format_recipe(source: &str) -> anyhow::Result<String> {
+        let kind = Kind::Recipe(Language::Italian);
+        let formatted = kind.format(source)?;
+        check_lossless(kind, source, &formatted)?;
+        Ok(formatted)
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let formatted = format_recipe(RECIPE).unwrap();
+        assert_ne!(formatted, RECIPE);
+        assert!(formatted.contains("# Preparazione"));
+        assert_eq!(format_recipe(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn rejects_dropping_a_key_or_some_text() {
+        let kind = Kind::Recipe(Language::Italian);
+        let without_key = RECIPE.replace("tools: [Pentola]\n", "");
+        let err = check_lossless(kind, RECIPE, &without_key).unwrap_err();
+        assert!(err.to_string().contains("`tools`"), "{err}");
+
+        let without_step = RECIPE.replace("2. Salare.\n", "");
+        let err = check_lossless(kind, RECIPE, &without_step).unwrap_err();
+        assert!(err.to_string().contains("(Salare.)"), "{err}");
+    }
+
+    #[test]
+    fn keeps_the_files_with_unknown_keys_and_headings() {
+        let sources = TempBook::new(&book([]), "format-unknown");
+        let unknown_key = sources.0.join("recipes/chiave.md");
+        let unknown_key_source = RECIPE.replace("tags: []", "tags: []\ncalorie: 300");
+        write(&unknown_key, &unknown_key_source).unwrap();
+        let unknown_heading = sources.0.join("recipes/titolo.md");
+        let unknown_heading_source = format!("{RECIPE}\n# Vino\n\nUn rosso.\n");
+        write(&unknown_heading, &unknown_heading_source).unwrap();
+
+        let err = format_book(&sources.0, false).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("`calorie`"), "{message}");
+        assert!(message.contains("rosso."), "{message}");
+        assert_eq!(read_to_string(&unknown_key).unwrap(), unknown_key_source);
+        assert_eq!(
+            read_to_string(&unknown_heading).unwrap(),
+            unknown_heading_source
+        );
+    }
+}
@@ -1,46 +1,74 @@
-use std::{fs::File, path::Path};
+use std::{fs::File, io::read_to_string, path::Path};
 
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_email::Email;
 use url::Url;
 
-use crate::parsers::markdown::Markdown;
+use super::language::Language;
+use crate::{diagnostic::InFile, parsers::markdown::Markdown};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct FrontMatter {
     pub title: Markdown,
     pub subtitle: Markdown,
     pub author: String,
+    #[schemars(with = "String")]
     pub email: Email,
     pub site: Url,
+    /// Language of the recipes, deciding the headings of their sections
+    #[serde(default, skip_serializing_if = "is_italian")]
+    pub language: Language,
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     pub modified: Option<DateTime<Utc>>,
 }
 impl FrontMatter {
     pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         log::info!("Loading front matter from {}", path.as_ref().display());
-        let f = File::open(&path).context("While opening file")?;
-        let mut frontmatters: FrontMatter =
-            serde_yaml::from_reader(&f).context("Cannot parse file")?;
+        let path = path.as_ref();
+        let f = File::open(path)
+            .context("While opening file")
+            .in_file(path, None)?;
+        let source = read_to_string(&f)
+            .context("While reading file")
+            .in_file(path, None)?;
+        let mut frontmatters: FrontMatter = serde_yaml::from_str(&source)
+            .context("Cannot parse file")
+            .in_file(path, Some(&source))?;
         frontmatters.modified = f
             .metadata()
             .and_then(|m| m.modified())
             .inspect_err(|err| {
-                log::warn!(
-                    "Cannot read the modified time of {}: {err}",
-                    path.as_ref().display()
-                )
+                log::warn!("Cannot read the modified time of {}: {err}", path.display())
             })
             .ok()
             .map(Into::into);
         Ok(frontmatters)
     }
 
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        log::info!("Saving front matter to {}", path.as_ref().display());
+        // the modified time is read from the file itself
+        let front_matter = Self {
+            modified: None,
+            ..self.clone()
+        };
+        serde_yaml::to_writer(
+            File::create(path).context("Cannot create file")?,
+            &front_matter,
+        )
+        .context("While writing file")
+    }
+
     #[must_use]
     pub fn modified(&self) -> Option<DateTime<Utc>> {
         self.modified
     }
 }
+
+fn is_italian(language: &Language) -> bool {
+    *language == Language::Italian
+}
@@ -1,14 +1,26 @@
-use std::{fs::File, io::read_to_string, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, write, File},
+    io::read_to_string,
+    path::Path,
+};
 
 use anyhow::Context;
 use chrono::{DateTime, Utc};
-use markdown::{Constructs, ParseOptions};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::parsers::{
-    headed_md::HeadedMarkdown, markdown::Markdown, title_separated_list::TitleSeparatedList, FromMd,
+use super::language::Language;
+use crate::{
+    diagnostic::{Errors, InFile},
+    parsers::{
+        headed_md::HeadedMarkdown,
+        markdown::{parse_headed, stringify, Markdown},
+        title_separated_list::TitleSeparatedList,
+        DisplayMd, FromMd,
+    },
 };
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Introduction {
     pub zen: HeadedMarkdown<Metas, TitleSeparatedList<Markdown, Markdown>>,
     pub prologue: HeadedMarkdown<Metas, Markdown>,
@@ -19,46 +31,78 @@ impl Introduction {
     pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         log::info!("Loading introduction from {}", path.as_ref().display());
 
-        fn load<T: FromMd>(path: &Path, name: &str) -> anyhow::Result<HeadedMarkdown<Metas, T>> {
-            let path = path.join(name);
-            log::debug!("Loading {}", path.display());
-            let f = File::open(&path).context("While opening file")?;
-            let mut content: HeadedMarkdown<Metas, T> = FromMd::parse(
-                markdown::to_mdast(
-                    &read_to_string(&f).context("While reading file")?,
-                    &ParseOptions {
-                        constructs: Constructs {
-                            frontmatter: true, // needed to load the yaml too
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
+        let path = path.as_ref();
+        let ((zen, prologue), (warnings, thanks)) = rayon::join(
+            || {
+                rayon::join(
+                    || load_page(path, "zen.md"),
+                    || load_page(path, "prologue.md"),
+                )
+            },
+            || {
+                rayon::join(
+                    || load_page(path, "warnings.md"),
+                    || load_page(path, "thanks.md"),
                 )
-                .expect("Normal markdown should always parse"),
-            )
-            .context("While parsing")?;
-            // adding modified date
-            if content.metas.modified.is_some() {
-                log::warn!("Setted `modified` value in the header will get ignored")
+            },
+        );
+
+        let mut errors = Errors::default();
+        let zen = errors.collect(zen);
+        let prologue = errors.collect(prologue);
+        let warnings = errors.collect(warnings);
+        let thanks = errors.collect(thanks);
+
+        match (zen, prologue, warnings, thanks) {
+            (Some(zen), Some(prologue), Some(warnings), Some(thanks)) => Ok(Self {
+                zen,
+                prologue,
+                warnings,
+                thanks,
+            }),
+            _ => Err(errors.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        log::info!("Saving introduction to {}", path.as_ref().display());
+
+        create_dir_all(path.as_ref()).context("Cannot create directory")?;
+        save_page(path.as_ref(), "zen.md", &self.zen).context("While saving zen")?;
+        save_page(path.as_ref(), "prologue.md", &self.prologue).context("While saving prologue")?;
+        save_page(path.as_ref(), "warnings.md", &self.warnings).context("While saving warnings")?;
+        save_page(path.as_ref(), "thanks.md", &self.thanks).context("While saving thanks")?;
+        Ok(())
+    }
+
+    /// All the markdown texts of the pages, that can contain links
+    #[must_use]
+    pub fn markdowns(&self) -> Vec<&Markdown> {
+        let mut mds = vec![];
+        for list in [&self.zen, &self.warnings] {
+            mds.push(&list.metas.title);
+            for item in &list.content.items {
+                mds.extend([&item.title, &item.content])
             }
-            content.metas.modified = f
-                .metadata()
-                .and_then(|m| m.modified())
-                .inspect_err(|err| {
-                    log::warn!("Cannot read the modified time of {}: {err}", path.display())
-                })
-                .ok()
-                .map(Into::into);
-
-            anyhow::Ok(content)
-        }
-
-        Ok(Self {
-            zen: load(path.as_ref(), "zen.md").context("While loading zen")?,
-            prologue: load(path.as_ref(), "prologue.md").context("While loading prologue")?,
-            warnings: load(path.as_ref(), "warnings.md").context("While loading warnings")?,
-            thanks: load(path.as_ref(), "thanks.md").context("While loading thanks")?,
-        })
+        }
+        for page in [&self.prologue, &self.thanks] {
+            mds.extend([&page.metas.title, &page.content])
+        }
+        mds
+    }
+
+    pub fn markdowns_mut(&mut self) -> Vec<&mut Markdown> {
+        let mut mds = vec![];
+        for list in [&mut self.zen, &mut self.warnings] {
+            mds.push(&mut list.metas.title);
+            for item in &mut list.content.items {
+                mds.extend([&mut item.title, &mut item.content])
+            }
+        }
+        for page in [&mut self.prologue, &mut self.thanks] {
+            mds.extend([&mut page.metas.title, &mut page.content])
+        }
+        mds
     }
 
     #[must_use]
@@ -79,7 +123,209 @@ impl Introduction {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Pages of the introduction translated in another language
+///
+/// The missing pages are taken from the main language. The translated files have the
+/// code of the language before the extension, like `zen.en.md`
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct IntroductionTranslation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zen: Option<HeadedMarkdown<Metas, TitleSeparatedList<Markdown, Markdown>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prologue: Option<HeadedMarkdown<Metas, Markdown>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<HeadedMarkdown<Metas, TitleSeparatedList<Markdown, Markdown>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thanks: Option<HeadedMarkdown<Metas, Markdown>>,
+}
+impl IntroductionTranslation {
+    /// Load the translated pages found in `path`
+    pub fn load(path: impl AsRef<Path>, language: Language) -> anyhow::Result<Self> {
+        fn load<T: FromMd>(
+            path: &Path,
+            name: &str,
+            errors: &mut Errors,
+        ) -> Option<HeadedMarkdown<Metas, T>> {
+            path.join(name)
+                .exists()
+                .then(|| errors.collect(load_page(path, name)))
+                .flatten()
+        }
+
+        let path = path.as_ref();
+        let code = language.code();
+        let mut errors = Errors::default();
+        let translation = Self {
+            zen: load(path, &format!("zen.{code}.md"), &mut errors),
+            prologue: load(path, &format!("prologue.{code}.md"), &mut errors),
+            warnings: load(path, &format!("warnings.{code}.md"), &mut errors),
+            thanks: load(path, &format!("thanks.{code}.md"), &mut errors),
+        };
+        errors.into_result(translation)
+    }
+
+    /// Load the translations of the introduction in all the languages but `main`
+    pub fn load_all(
+        path: impl AsRef<Path>,
+        main: Language,
+    ) -> anyhow::Result<BTreeMap<Language, Self>> {
+        let mut errors = Errors::default();
+        let mut translations = BTreeMap::new();
+        for language in Language::ALL.into_iter().filter(|l| *l != main) {
+            if let Some(translation) = errors.collect(Self::load(path.as_ref(), language)) {
+                if !translation.is_empty() {
+                    translations.insert(language, translation);
+                }
+            }
+        }
+        errors.into_result(translations)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, language: Language) -> anyhow::Result<()> {
+        log::info!(
+            "Saving the `{language}` introduction to {}",
+            path.as_ref().display()
+        );
+        let path = path.as_ref();
+        let code = language.code();
+        create_dir_all(path).context("Cannot create directory")?;
+        if let Some(zen) = &self.zen {
+            save_page(path, &format!("zen.{code}.md"), zen).context("While saving zen")?;
+        }
+        if let Some(prologue) = &self.prologue {
+            save_page(path, &format!("prologue.{code}.md"), prologue)
+                .context("While saving prologue")?;
+        }
+        if let Some(warnings) = &self.warnings {
+            save_page(path, &format!("warnings.{code}.md"), warnings)
+                .context("While saving warnings")?;
+        }
+        if let Some(thanks) = &self.thanks {
+            save_page(path, &format!("thanks.{code}.md"), thanks).context("While saving thanks")?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.zen.is_none()
+            && self.prologue.is_none()
+            && self.warnings.is_none()
+            && self.thanks.is_none()
+    }
+
+    /// Replace the pages of `introduction` with the translated ones
+    pub fn apply(&self, introduction: &mut Introduction) {
+        if let Some(zen) = &self.zen {
+            introduction.zen = zen.clone()
+        }
+        if let Some(prologue) = &self.prologue {
+            introduction.prologue = prologue.clone()
+        }
+        if let Some(warnings) = &self.warnings {
+            introduction.warnings = warnings.clone()
+        }
+        if let Some(thanks) = &self.thanks {
+            introduction.thanks = thanks.clone()
+        }
+    }
+
+    /// The translated pages with their original, by name
+    #[must_use]
+    pub fn pages<'s>(
+        &'s self,
+        original: &'s Introduction,
+    ) -> Vec<(&'static str, &'s Metas, &'s Metas)> {
+        let mut pages = vec![];
+        if let Some(zen) = &self.zen {
+            pages.push(("zen", &zen.metas, &original.zen.metas))
+        }
+        if let Some(prologue) = &self.prologue {
+            pages.push(("prologue", &prologue.metas, &original.prologue.metas))
+        }
+        if let Some(warnings) = &self.warnings {
+            pages.push(("warnings", &warnings.metas, &original.warnings.metas))
+        }
+        if let Some(thanks) = &self.thanks {
+            pages.push(("thanks", &thanks.metas, &original.thanks.metas))
+        }
+        pages
+    }
+
+    /// All the markdown texts of the translated pages, that can contain links
+    #[must_use]
+    pub fn markdowns(&self) -> Vec<&Markdown> {
+        let mut mds = vec![];
+        for list in [&self.zen, &self.warnings].into_iter().flatten() {
+            mds.push(&list.metas.title);
+            for item in &list.content.items {
+                mds.extend([&item.title, &item.content])
+            }
+        }
+        for page in [&self.prologue, &self.thanks].into_iter().flatten() {
+            mds.extend([&page.metas.title, &page.content])
+        }
+        mds
+    }
+
+    pub fn markdowns_mut(&mut self) -> Vec<&mut Markdown> {
+        let mut mds = vec![];
+        for list in [&mut self.zen, &mut self.warnings].into_iter().flatten() {
+            mds.push(&mut list.metas.title);
+            for item in &mut list.content.items {
+                mds.extend([&mut item.title, &mut item.content])
+            }
+        }
+        for page in [&mut self.prologue, &mut self.thanks].into_iter().flatten() {
+            mds.extend([&mut page.metas.title, &mut page.content])
+        }
+        mds
+    }
+}
+
+/// Load a page of the introduction
+fn load_page<T: FromMd>(path: &Path, name: &str) -> anyhow::Result<HeadedMarkdown<Metas, T>> {
+    let path = path.join(name);
+    log::debug!("Loading {}", path.display());
+    let f = File::open(&path)
+        .context("While opening file")
+        .in_file(&path, None)?;
+    let source = read_to_string(&f)
+        .context("While reading file")
+        .in_file(&path, None)?;
+    let mut content: HeadedMarkdown<Metas, T> = FromMd::parse(parse_headed(&source))
+        .context("While parsing")
+        .in_file(&path, Some(&source))?;
+    // adding modified date
+    if content.metas.modified.is_some() {
+        log::warn!("Setted `modified` value in the header will get ignored")
+    }
+    content.metas.modified = f
+        .metadata()
+        .and_then(|m| m.modified())
+        .inspect_err(|err| log::warn!("Cannot read the modified time of {}: {err}", path.display()))
+        .ok()
+        .map(Into::into);
+
+    anyhow::Ok(content)
+}
+
+/// Save a page of the introduction
+fn save_page<T: DisplayMd + Clone>(
+    path: &Path,
+    name: &str,
+    content: &HeadedMarkdown<Metas, T>,
+) -> anyhow::Result<()> {
+    let path = path.join(name);
+    log::debug!("Saving {}", path.display());
+    let mut content = content.clone();
+    // the modified time is read from the file itself
+    content.metas.modified = None;
+    let md = content.fmt().context("While formatting")?;
+    write(&path, stringify(&md) + "\n").context("Cannot write file")
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Metas {
     pub title: Markdown,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -0,0 +1,159 @@
+//! Language of the book, deciding the headings of the recipe sections and the labels
+//! written by the frontends
+
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    ValueEnum,
+)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "it")]
+    #[value(name = "it")]
+    Italian,
+    #[serde(rename = "en")]
+    #[value(name = "en")]
+    English,
+}
+
+/// The sections of a recipe body that start with a heading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// The steps of the preparation
+    Preparation,
+    /// Variations on the recipe
+    Variations,
+}
+
+/// The words the frontends write around the content of the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Introduction,
+    Recipes,
+    Tags,
+    /// Heading of the tags under another one
+    Subtags,
+    /// Before the tag containing another one
+    PartOf,
+    Ingredients,
+    Tools,
+    Optional,
+    Time,
+    PrepTime,
+    CookTime,
+    RestTime,
+    /// After the time including the recipes used as ingredients
+    WithSubrecipes,
+    ShoppingList,
+    /// Heading of the optional ingredients of the shopping list
+    OptionalItems,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::Italian, Language::English];
+
+    /// The code of the language, used in the names of the translated files
+    #[must_use]
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Italian => "it",
+            Language::English => "en",
+        }
+    }
+
+    /// The language with the given code
+    #[must_use]
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code() == code)
+    }
+
+    /// The headings accepted for a section, the first being the one that is written
+    #[must_use]
+    pub fn headings(self, section: Section) -> &'static [&'static str] {
+        match (self, section) {
+            (Language::Italian, Section::Preparation) => &["Preparazione", "Procedimento"],
+            (Language::Italian, Section::Variations) => &["Modifiche e aggiunte", "Varianti"],
+            (Language::English, Section::Preparation) => &["Preparation", "Method"],
+            (Language::English, Section::Variations) => &["Variations", "Changes and additions"],
+        }
+    }
+
+    /// The heading written for a section
+    #[must_use]
+    pub fn heading(self, section: Section) -> &'static str {
+        self.headings(section)[0]
+    }
+
+    /// The text of a label
+    #[must_use]
+    pub fn label(self, label: Label) -> &'static str {
+        match (self, label) {
+            (Language::Italian, Label::Introduction) => "Introduzione",
+            (Language::Italian, Label::Recipes) => "Ricette",
+            (Language::Italian, Label::Tags) => "Tag",
+            (Language::Italian, Label::Subtags) => "Sottocategorie",
+            (Language::Italian, Label::PartOf) => "Fa parte di",
+            (Language::Italian, Label::Ingredients) => "Ingredienti",
+            (Language::Italian, Label::Tools) => "Strumenti",
+            (Language::Italian, Label::Optional) => "opzionale",
+            (Language::Italian, Label::Time) => "Tempo",
+            (Language::Italian, Label::PrepTime) => "preparazione",
+            (Language::Italian, Label::CookTime) => "cottura",
+            (Language::Italian, Label::RestTime) => "riposo",
+            (Language::Italian, Label::WithSubrecipes) => "con le preparazioni di base",
+            (Language::Italian, Label::ShoppingList) => "Lista della spesa",
+            (Language::Italian, Label::OptionalItems) => "Opzionali",
+            (Language::English, Label::Introduction) => "Introduction",
+            (Language::English, Label::Recipes) => "Recipes",
+            (Language::English, Label::Tags) => "Tags",
+            (Language::English, Label::Subtags) => "Subcategories",
+            (Language::English, Label::PartOf) => "Part of",
+            (Language::English, Label::Ingredients) => "Ingredients",
+            (Language::English, Label::Tools) => "Tools",
+            (Language::English, Label::Optional) => "optional",
+            (Language::English, Label::Time) => "Time",
+            (Language::English, Label::PrepTime) => "preparation",
+            (Language::English, Label::CookTime) => "cooking",
+            (Language::English, Label::RestTime) => "resting",
+            (Language::English, Label::WithSubrecipes) => "with the base recipes",
+            (Language::English, Label::ShoppingList) => "Shopping list",
+            (Language::English, Label::OptionalItems) => "Optional",
+        }
+    }
+
+    /// The section starting with `heading`, ignoring case and surrounding spaces
+    #[must_use]
+    pub fn section(self, heading: &str) -> Option<Section> {
+        let heading = heading.trim().to_lowercase();
+        [Section::Preparation, Section::Variations]
+            .into_iter()
+            .find(|section| {
+                self.headings(*section)
+                    .iter()
+                    .any(|alias| alias.to_lowercase() == heading)
+            })
+    }
+}
+impl Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
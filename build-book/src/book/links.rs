@@ -0,0 +1,346 @@
+//! Links between recipes
+//!
+//! A recipe can be linked with `[[name]]`, `[[name|text]]`, or with a normal link to
+//! its source file like `[text](name.md)`. Frontends rewrite them into their own kind
+//! of link.
+
+use std::{collections::BTreeMap, mem, path::PathBuf};
+
+use anyhow::anyhow;
+use lazy_regex::{lazy_regex, regex::escape, Lazy, Regex};
+use markdown::mdast::{Link, Node, Text};
+
+use super::{
+    is_unknown_recipe,
+    language::Language,
+    recipe::{Ingredient, Recipe},
+    recipe_file, translation, Book,
+};
+use crate::{
+    diagnostic::{find_in_files, Errors},
+    parsers::markdown::Markdown,
+};
+
+/// `[[name]]` or `[[name|text]]`
+static WIKI_LINK: Lazy<Regex> = lazy_regex!(r"\[\[([^\[\]|]+)(?:\|([^\[\]]*))?\]\]");
+
+/// The recipe a link url points to, if any
+///
+/// Besides the source files, the `#recipes/name` anchors written by the yaml and json
+/// frontends are recognized, so their output can be imported back. Links to the
+/// translated files, like `name.en.md`, point to the recipe itself.
+#[must_use]
+pub fn recipe_target(url: &str) -> Option<&str> {
+    if let Some(name) = url.strip_prefix("#recipes/") {
+        return Some(name);
+    }
+    if url.contains(':') {
+        return None; // absolute urls
+    }
+    let path = url.split('#').next()?;
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    if !(dir.is_empty() || dir == "." || dir == "recipes" || dir.ends_with("/recipes")) {
+        return None;
+    }
+    // a link to a translation points to the same recipe
+    file.strip_suffix(".md")
+        .map(|name| translation::split_name(name).0)
+        .filter(|name| !name.is_empty())
+}
+
+/// The names of the recipes linked from some markdown
+#[must_use]
+pub fn linked(md: &Markdown) -> Vec<String> {
+    fn visit(node: &Node, links: &mut Vec<String>) {
+        match node {
+            Node::Text(Text { value, .. }) => links.extend(
+                WIKI_LINK
+                    .captures_iter(value)
+                    .map(|c| c[1].trim().to_owned()),
+            ),
+            Node::Link(Link { url, .. }) => links.extend(recipe_target(url).map(ToOwned::to_owned)),
+            node => {
+                for child in node.children().into_iter().flatten() {
+                    visit(child, links)
+                }
+            }
+        }
+    }
+    let mut links = vec![];
+    visit(&md.0, &mut links);
+    links
+}
+
+/// Matches the links to the recipe `name` in a source file, in all their forms
+#[must_use]
+pub fn pattern(name: &str) -> Regex {
+    let name = escape(name);
+    Regex::new(&format!(
+        r"(\[\[\s*{name}\s*(?:\|[^\[\]]*)?\]\]|\]\((?:#recipes/{name}|(?:[^)\s]*/)?{name}(?:\.[a-z]{{2}})?\.md(?:#[^)\s]*)?)\))"
+    ))
+    .expect("the name is escaped")
+}
+
+/// Check that the links point to existing recipes
+///
+/// If the book was loaded from a directory, the errors point to the links in the files.
+pub fn check(book: &Book) -> anyhow::Result<()> {
+    let mut errors = Errors::default();
+    let recipe_file = |name: &str, language: Option<Language>| {
+        book.root
+            .as_deref()
+            .map(|root| recipe_file(root, name, language))
+    };
+    let introduction_files = |language: Option<Language>| -> Vec<PathBuf> {
+        let Some(root) = &book.root else {
+            return vec![];
+        };
+        ["zen", "prologue", "warnings", "thanks"]
+            .into_iter()
+            .map(|page| match language {
+                Some(language) => root.join(format!("introduction/{page}.{language}.md")),
+                None => root.join(format!("introduction/{page}.md")),
+            })
+            .collect()
+    };
+
+    for (name, recipe) in &book.recipes {
+        // the recipes used as ingredients are checked with the other ingredients
+        let ingredients: Vec<_> = recipe
+            .ingredients
+            .iter()
+            .filter_map(Ingredient::recipe)
+            .collect();
+        for target in recipe
+            .markdowns()
+            .into_iter()
+            .flat_map(linked)
+            .filter(|target| !ingredients.contains(target))
+        {
+            if is_unknown_recipe(&book.recipes, book.root.as_deref(), &target) {
+                errors.push(find_in_files(
+                    recipe_file(name, None),
+                    &pattern(&target),
+                    anyhow!("Recipe `{name}` links to the unknown recipe `{target}`"),
+                ))
+            }
+        }
+    }
+    for target in book.introduction.markdowns().into_iter().flat_map(linked) {
+        if is_unknown_recipe(&book.recipes, book.root.as_deref(), &target) {
+            errors.push(find_in_files(
+                introduction_files(None),
+                &pattern(&target),
+                anyhow!("The introduction links to the unknown recipe `{target}`"),
+            ))
+        }
+    }
+    for (language, translation) in &book.translations {
+        for (name, recipe) in &translation.recipes {
+            for target in recipe.markdowns().into_iter().flat_map(linked) {
+                if is_unknown_recipe(&book.recipes, book.root.as_deref(), &target) {
+                    errors.push(find_in_files(
+                        recipe_file(name, Some(*language)),
+                        &pattern(&target),
+                        anyhow!(
+                            "Recipe `{name}` in `{language}` links to the unknown recipe `{target}`"
+                        ),
+                    ))
+                }
+            }
+        }
+        for target in translation
+            .introduction
+            .markdowns()
+            .into_iter()
+            .flat_map(linked)
+        {
+            if is_unknown_recipe(&book.recipes, book.root.as_deref(), &target) {
+                errors.push(find_in_files(
+                    introduction_files(Some(*language)),
+                    &pattern(&target),
+                    anyhow!(
+                        "The introduction in `{language}` links to the unknown recipe `{target}`"
+                    ),
+                ))
+            }
+        }
+    }
+    errors.into_result(())
+}
+
+/// Point all the links to recipes to `url(name)`, turning `[[name]]` into normal links
+///
+/// Links to unknown recipes are left as they are. The translations link with the
+/// translated titles of the recipes.
+pub fn rewrite(book: &mut Book, url: impl Fn(&str) -> String) {
+    let titles = |recipes: &BTreeMap<String, Recipe>| -> BTreeMap<String, String> {
+        recipes
+            .iter()
+            .map(|(name, recipe)| (name.clone(), recipe.name.0.to_string().trim().to_owned()))
+            .collect()
+    };
+    let main_titles = titles(&book.recipes);
+    let translated_titles: BTreeMap<Language, _> = book
+        .translations
+        .keys()
+        .map(|language| (*language, titles(&book.translated(*language).recipes)))
+        .collect();
+
+    let mut mds = book.introduction.markdowns_mut();
+    for recipe in book.recipes.values_mut() {
+        mds.extend(recipe.markdowns_mut())
+    }
+    rewrite_mds(mds, &main_titles, &url);
+
+    for (language, translation) in &mut book.translations {
+        let mut mds = translation.introduction.markdowns_mut();
+        for recipe in translation.recipes.values_mut() {
+            mds.extend(recipe.markdowns_mut())
+        }
+        rewrite_mds(mds, &translated_titles[language], &url);
+    }
+}
+
+fn rewrite_mds(
+    mds: Vec<&mut Markdown>,
+    titles: &BTreeMap<String, String>,
+    url: &impl Fn(&str) -> String,
+) {
+    let target = |name: &str| titles.get(name).map(|title| (url(name), title.as_str()));
+    for md in mds {
+        if let Some(children) = md.0.children_mut() {
+            rewrite_children(children, &target)
+        }
+    }
+}
+
+fn rewrite_children<'t>(
+    children: &mut Vec<Node>,
+    target: &impl Fn(&str) -> Option<(String, &'t str)>,
+) {
+    for mut node in mem::take(children) {
+        match &mut node {
+            Node::Text(Text { value, position }) => {
+                let mut rest = value.as_str();
+                while let Some(c) = WIKI_LINK.captures(rest) {
+                    let whole = c.get(0).unwrap();
+                    let Some((url, title)) = target(c[1].trim()) else {
+                        children.push(Node::Text(Text {
+                            value: rest[..whole.end()].to_owned(),
+                            position: None,
+                        }));
+                        rest = &rest[whole.end()..];
+                        continue;
+                    };
+                    if whole.start() > 0 {
+                        children.push(Node::Text(Text {
+                            value: rest[..whole.start()].to_owned(),
+                            position: None,
+                        }))
+                    }
+                    let text = c.get(2).map_or(title, |text| text.as_str()).trim();
+                    children.push(Node::Link(Link {
+                        children: vec![Node::Text(Text {
+                            value: text.to_owned(),
+                            position: None,
+                        })],
+                        position: None,
+                        url,
+                        title: None,
+                    }));
+                    rest = &rest[whole.end()..];
+                }
+                if rest.len() == value.len() {
+                    children.push(Node::Text(Text {
+                        value: rest.to_owned(),
+                        position: position.clone(),
+                    }))
+                } else if !rest.is_empty() {
+                    children.push(Node::Text(Text {
+                        value: rest.to_owned(),
+                        position: None,
+                    }))
+                }
+                continue;
+            }
+            Node::Link(Link { url, .. }) => {
+                if let Some((new_url, _)) = recipe_target(url).and_then(target) {
+                    *url = new_url
+                }
+            }
+            node => {
+                if let Some(children) = node.children_mut() {
+                    rewrite_children(children, target)
+                }
+            }
+        }
+        children.push(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe, TempBook};
+
+    fn md(source: &str) -> Markdown {
+        serde_yaml::from_str(&format!("{source:?}")).unwrap()
+    }
+
+    #[test]
+    fn targets_of_the_urls() {
+        assert_eq!(recipe_target("sugo.md"), Some("sugo"));
+        assert_eq!(recipe_target("./sugo.md#preparazione"), Some("sugo"));
+        assert_eq!(recipe_target("../recipes/sugo.en.md"), Some("sugo"));
+        assert_eq!(recipe_target("#recipes/sugo"), Some("sugo"));
+        assert_eq!(recipe_target("https://example.com/sugo.md"), None);
+        assert_eq!(recipe_target("../introduction/zen.md"), None);
+        assert_eq!(recipe_target("foto.jpg"), None);
+    }
+
+    #[test]
+    fn links_in_all_their_forms() {
+        let text = md("Con il [[sugo]], il [[ragu|ragù]] o il [pesto](pesto.md).");
+        assert_eq!(linked(&text), ["sugo", "ragu", "pesto"]);
+        let source = "Con il [[sugo]], il [[ragu|ragù]] o il [pesto](pesto.md).";
+        for name in ["sugo", "ragu", "pesto"] {
+            assert!(pattern(name).is_match(source), "{name}");
+        }
+        assert!(!pattern("sug").is_match(source));
+    }
+
+    #[test]
+    fn links_are_rewritten_to_the_titles() {
+        let mut pasta = recipe("time: 10m\ningredients: [Pasta 100 g]");
+        pasta.descr = md("Con il [[sugo]], il [[sugo|suo sugo]] o il [[pesto]].");
+        let mut sugo = recipe("time: 10m\ningredients: [Pomodoro 100 g]");
+        sugo.name = md("Sugo di pomodoro");
+        let mut book = book([("pasta", pasta), ("sugo", sugo)]);
+
+        rewrite(&mut book, |name| format!("{name}.html"));
+        assert_eq!(
+            serde_json::to_value(&book.recipes["pasta"].descr).unwrap(),
+            "Con il [Sugo di pomodoro](sugo.html), il [suo sugo](sugo.html) o il [[pesto]]."
+        );
+    }
+
+    #[test]
+    fn unknown_links_point_to_their_line() {
+        let mut pasta = recipe("time: 10m\ningredients: [Pasta 100 g]");
+        pasta.descr = md("Con il [[pesto]].");
+        let book = book([("pasta", pasta)]);
+        let sources = TempBook::new(&book, "unknown-link");
+        let mut loaded = book.clone();
+        loaded.root = Some(sources.0.clone());
+
+        let err = check(&loaded).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(
+            message.contains("links to the unknown recipe `pesto`"),
+            "{message}"
+        );
+        assert!(message.contains("pasta.md:"), "{message}");
+        assert!(message.contains("^^^^^^^^^"), "{message}");
+    }
+}
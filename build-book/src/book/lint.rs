@@ -0,0 +1,530 @@
+//! Checks on the content of the recipes, reported as warnings
+//!
+//! Unlike loading errors, these do not prevent the book from being built, as the
+//! matching of words is only approximate.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use chrono::Duration;
+use lazy_regex::{regex::escape, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    recipe::{Ingredient, Recipe},
+    recipe_file, Book,
+};
+use crate::{diagnostic::find_in_files, parsers::markdown::Markdown};
+
+/// Ingredients that are recognized in the steps even if no recipe declares them
+const COMMON_INGREDIENTS: &[&str] = &[
+    "aceto",
+    "acciuga",
+    "aglio",
+    "basilico",
+    "burro",
+    "cappero",
+    "carota",
+    "cioccolato",
+    "cipolla",
+    "farina",
+    "formaggio",
+    "fungo",
+    "guanciale",
+    "latte",
+    "limone",
+    "lievito",
+    "melanzana",
+    "miele",
+    "mozzarella",
+    "olio",
+    "oliva",
+    "origano",
+    "pancetta",
+    "pane",
+    "panna",
+    "parmigiano",
+    "pasta",
+    "patata",
+    "pecorino",
+    "pepe",
+    "peperoncino",
+    "peperone",
+    "pomodoro",
+    "prezzemolo",
+    "prosciutto",
+    "riso",
+    "rosmarino",
+    "sale",
+    "salsiccia",
+    "sedano",
+    "soia",
+    "tonno",
+    "uovo",
+    "vino",
+    "zucchero",
+    "zucchina",
+];
+
+/// Tools that are recognized in the steps even if no recipe declares them
+const COMMON_TOOLS: &[&str] = &[
+    "casseruola",
+    "ciotola",
+    "colino",
+    "forno",
+    "fornello",
+    "frullatore",
+    "frusta",
+    "grattugia",
+    "mestolo",
+    "padella",
+    "pentola",
+    "pirofila",
+    "scolapasta",
+    "tagliere",
+    "tegame",
+    "teglia",
+];
+
+/// Words that carry no meaning when matching names
+const STOPWORDS: &[&str] = &[
+    "alla", "allo", "che", "con", "dei", "del", "della", "delle", "dello", "gli", "nel", "nella",
+    "per", "sul", "sulla", "una", "uno",
+];
+
+/// The kinds of warnings
+///
+/// The kebab-case names are used in the `allow` key of the recipes to silence a warning
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// An ingredient that no step uses
+    UnusedIngredient,
+    /// A step using an ingredient that is not declared
+    UndeclaredIngredient,
+    /// A tool that no step uses
+    UnusedTool,
+    /// A step using a tool that is not declared
+    UndeclaredTool,
+    /// Steps taking much longer than the declared time
+    TimeMismatch,
+    /// A total time shorter than the preparation, cooking and resting times together
+    TotalTooShort,
+    /// A key of the header that is not recognized, and is ignored
+    UnknownKey,
+    /// A heading that is not a section of the recipes, and whose content is ignored
+    UnknownHeading,
+}
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::UnusedIngredient => "unused-ingredient",
+            Kind::UndeclaredIngredient => "undeclared-ingredient",
+            Kind::UnusedTool => "unused-tool",
+            Kind::UndeclaredTool => "undeclared-tool",
+            Kind::TimeMismatch => "time-mismatch",
+            Kind::TotalTooShort => "total-too-short",
+            Kind::UnknownKey => "unknown-key",
+            Kind::UnknownHeading => "unknown-heading",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub recipe: String,
+    pub kind: Kind,
+    /// What the warning is about, like the name of the ingredient
+    pub subject: String,
+}
+impl Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            recipe,
+            kind,
+            subject,
+        } = self;
+        write!(f, "Recipe `{recipe}`: ")?;
+        match kind {
+            Kind::UnusedIngredient => write!(
+                f,
+                "the ingredient `{subject}` is never used in the preparation"
+            )?,
+            Kind::UndeclaredIngredient => write!(
+                f,
+                "the preparation uses `{subject}`, that is not among the ingredients"
+            )?,
+            Kind::UnusedTool => write!(f, "the tool `{subject}` is never used in the preparation")?,
+            Kind::UndeclaredTool => write!(
+                f,
+                "the preparation uses `{subject}`, that is not among the tools"
+            )?,
+            Kind::TimeMismatch => write!(
+                f,
+                "the steps take at least `{subject}`, much more than the declared time"
+            )?,
+            Kind::TotalTooShort => write!(
+                f,
+                "the preparation, cooking and resting times add up to `{subject}`, more than the total time"
+            )?,
+            Kind::UnknownKey => write!(
+                f,
+                "unknown key `{subject}` in the header, it is ignored"
+            )?,
+            Kind::UnknownHeading => write!(
+                f,
+                "`{subject}` is not a section of the recipes, its content is ignored"
+            )?,
+        }
+        write!(f, " [{kind}]")
+    }
+}
+impl Warning {
+    /// The warning pointing at the part of the recipe source it is about, if the book
+    /// was loaded from `root`
+    #[must_use]
+    pub fn in_source(&self, root: Option<&Path>) -> anyhow::Error {
+        let subject = escape(&self.subject);
+        let pattern = match self.kind {
+            Kind::UnusedIngredient | Kind::UnusedTool => {
+                format!(r#"(?m)^[ \t]*-[ \t]*["']?({subject}.*?)["']?[ \t]*$"#)
+            }
+            Kind::UndeclaredIngredient | Kind::UndeclaredTool => {
+                format!(r"(?im)^[ \t]*\d+\.[ \t].*?\b({subject})\b")
+            }
+            Kind::TimeMismatch => r"(?m)^(time|total|prep|cook|rest)[ \t]*:".to_owned(),
+            Kind::TotalTooShort => r"(?m)^(time|total)[ \t]*:".to_owned(),
+            Kind::UnknownKey => format!(r#"(?m)^["']?({subject})["']?[ \t]*:"#),
+            Kind::UnknownHeading => format!(r"(?m)^#[ \t]+({subject})[ \t]*$"),
+        };
+        let pattern = Regex::new(&pattern).expect("the subject is escaped");
+        find_in_files(
+            root.map(|root| recipe_file(root, &self.recipe, None)),
+            &pattern,
+            anyhow!("{self}"),
+        )
+    }
+}
+
+/// Run all the checks on the recipes of the book
+///
+/// Warnings listed in the `allow` key of a recipe are not reported
+#[must_use]
+pub fn lint(book: &Book) -> Vec<Warning> {
+    // names declared anywhere in the book are recognized in all the recipes, but only
+    // as a whole, so `Pasta fresca` does not make every `fresca` an ingredient
+    let mut ingredients: BTreeSet<Name> =
+        COMMON_INGREDIENTS.iter().map(|w| vec![stem(w)]).collect();
+    let mut tools: BTreeSet<Name> = COMMON_TOOLS.iter().map(|w| vec![stem(w)]).collect();
+    for recipe in book.recipes.values() {
+        for ingredient in &recipe.ingredients {
+            ingredients.extend(ingredient_names(book, ingredient));
+        }
+        for tool in &recipe.tools {
+            tools.insert(as_name(tool));
+        }
+    }
+    ingredients.retain(|name| !name.is_empty());
+    tools.retain(|name| !name.is_empty());
+
+    let mut warnings = vec![];
+    for (name, recipe) in &book.recipes {
+        let steps: Vec<_> = recipe
+            .preparazione
+            .iter()
+            .map(|step| word_list(&step.text))
+            .collect();
+
+        let declared_ingredients = recipe.ingredients.iter().map(|ingredient| {
+            let mut names = ingredient_words(book, ingredient);
+            if let Some(comment) = ingredient.comment() {
+                names.extend(words(comment))
+            }
+            (ingredient.name(), names)
+        });
+        let found = cross_check(
+            declared_ingredients,
+            &steps,
+            &ingredients,
+            (Kind::UnusedIngredient, Kind::UndeclaredIngredient),
+        )
+        .chain(cross_check(
+            recipe.tools.iter().map(|tool| (tool, words(tool))),
+            &steps,
+            &tools,
+            (Kind::UnusedTool, Kind::UndeclaredTool),
+        ))
+        .chain(check_time(recipe))
+        .chain(check_total(recipe))
+        .chain(
+            recipe
+                .unknown_keys
+                .iter()
+                .map(|key| (Kind::UnknownKey, key.clone())),
+        )
+        .chain(
+            recipe
+                .unknown_headings
+                .iter()
+                .map(|heading| (Kind::UnknownHeading, heading.clone())),
+        );
+
+        warnings.extend(
+            found
+                .filter(|(kind, subject)| !recipe.allows(*kind, subject))
+                .map(|(kind, subject)| Warning {
+                    recipe: name.to_owned(),
+                    kind,
+                    subject,
+                }),
+        )
+    }
+    warnings
+}
+
+/// A name, as the stems of its meaningful words
+type Name = Vec<String>;
+
+/// Match the things declared by a recipe with the words of its steps
+///
+/// Returns the declared things that are never mentioned, and the names of the
+/// vocabulary that the steps mention but were not declared
+fn cross_check<'a>(
+    declared: impl IntoIterator<Item = (&'a Markdown, BTreeMap<String, String>)>,
+    steps: &[Vec<(String, String)>],
+    vocabulary: &BTreeSet<Name>,
+    (unused, undeclared): (Kind, Kind),
+) -> impl Iterator<Item = (Kind, String)> {
+    let stems: BTreeSet<&String> = steps.iter().flatten().map(|(stem, _)| stem).collect();
+    let mut found = vec![];
+    let mut declared_words = BTreeSet::new();
+    for (name, words) in declared {
+        if !words.keys().any(|word| stems.contains(word)) {
+            found.push((unused, name.0.to_string().trim().to_owned()))
+        }
+        declared_words.extend(words.into_keys());
+    }
+    let mut mentioned = BTreeMap::new();
+    for step in steps {
+        for name in vocabulary {
+            if name.iter().all(|stem| declared_words.contains(stem)) {
+                continue;
+            }
+            for window in step.windows(name.len()) {
+                if window.iter().map(|(stem, _)| stem).eq(name) {
+                    mentioned.entry(name).or_insert_with(|| {
+                        let words: Vec<_> = window.iter().map(|(_, word)| word.as_str()).collect();
+                        words.join(" ")
+                    });
+                }
+            }
+        }
+    }
+    found.extend(mentioned.into_values().map(|words| (undeclared, words)));
+    found.into_iter()
+}
+
+/// The names of an ingredient, that is also called as the recipe it stands for
+fn ingredient_names(book: &Book, ingredient: &Ingredient) -> Vec<Name> {
+    let mut names = vec![as_name(ingredient.name())];
+    if let Some(recipe) = ingredient.recipe().and_then(|name| book.recipes.get(&name)) {
+        names.push(as_name(&recipe.name))
+    }
+    names
+}
+
+/// The words naming an ingredient, including the name of the recipe it stands for
+fn ingredient_words(book: &Book, ingredient: &Ingredient) -> BTreeMap<String, String> {
+    let mut names = words(ingredient.name());
+    if let Some(recipe) = ingredient.recipe().and_then(|name| book.recipes.get(&name)) {
+        names.extend(words(&recipe.name))
+    }
+    names
+}
+
+/// Compare the durations mentioned in the steps with the declared time
+///
+/// Only steps taking way longer are reported, as the steps rarely mention all the
+/// time needed, and some of them can be done at the same time
+fn check_time(recipe: &Recipe) -> Option<(Kind, String)> {
+    let steps = recipe
+        .preparazione
+        .iter()
+        .flat_map(|step| &step.times)
+        .fold(Duration::zero(), |sum, time| sum + time.min);
+    (steps > recipe.time.total() * 3 / 2).then(|| {
+        (
+            Kind::TimeMismatch,
+            humantime::format_duration(steps.to_std().unwrap_or_default()).to_string(),
+        )
+    })
+}
+
+/// Check that the total time is enough for the preparation, cooking and resting times
+fn check_total(recipe: &Recipe) -> Option<(Kind, String)> {
+    let sum = recipe.time.sum()?;
+    (recipe.time.total() < sum).then(|| {
+        (
+            Kind::TotalTooShort,
+            humantime::format_duration(sum.to_std().unwrap_or_default()).to_string(),
+        )
+    })
+}
+
+/// The meaningful words of some markdown, indexed by their stem
+fn words(md: &Markdown) -> BTreeMap<String, String> {
+    word_list(md).into_iter().collect()
+}
+
+/// The meaningful words of some markdown in order, with their stem
+fn word_list(md: &Markdown) -> Vec<(String, String)> {
+    md.0.to_string()
+        .split(|c: char| !c.is_alphabetic())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() >= 3 && !STOPWORDS.contains(&word.as_str()))
+        .map(|word| (stem(&word), word))
+        .collect()
+}
+
+/// The stems of the meaningful words of a name
+fn as_name(md: &Markdown) -> Name {
+    word_list(md).into_iter().map(|(stem, _)| stem).collect()
+}
+
+/// Reduce an italian word to a stem shared by its plural and gender variations
+///
+/// This drops the final vowels, so `pomodoro` and `pomodori` or `salato` and `salata`
+/// are the same, and the `h` added before them in plurals like `funghi`. The endings of
+/// the participles and of the verbs are dropped too, so `salata` and `salare` share the
+/// stem of `sale`, and `bollito` the one of `bollire`.
+fn stem(word: &str) -> String {
+    let word = word.to_lowercase();
+    let stem = word.trim_end_matches(['a', 'e', 'i', 'o']);
+    let stem = match stem.strip_suffix('h') {
+        Some(stripped) if stripped.ends_with(['c', 'g']) => stripped,
+        _ => stem,
+    };
+    let stem = ["at", "it", "ut", "ar", "er", "ir"]
+        .into_iter()
+        .find_map(|ending| stem.strip_suffix(ending))
+        .filter(|root| root.chars().count() >= 3)
+        .unwrap_or(stem);
+    if stem.is_empty() {
+        word
+    } else {
+        stem.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe};
+
+    #[test]
+    fn stems_are_shared_by_plural_and_gender() {
+        assert_eq!(stem("pomodoro"), stem("pomodori"));
+        assert_eq!(stem("salato"), stem("salata"));
+        assert_eq!(stem("cipolla"), stem("cipolle"));
+        assert_eq!(stem("Pentola"), stem("pentole"));
+    }
+
+    #[test]
+    fn stems_drop_the_h_of_the_plurals() {
+        assert_eq!(stem("fungo"), stem("funghi"));
+        assert_eq!(stem("amica"), stem("amiche"));
+        assert_eq!(stem("forchetta"), "forchett");
+    }
+
+    #[test]
+    fn stems_drop_the_endings_of_participles_and_verbs() {
+        assert_eq!(stem("Sale"), stem("salata"));
+        assert_eq!(stem("sale"), stem("salare"));
+        assert_eq!(stem("bollito"), stem("bollire"));
+        assert_eq!(stem("patata"), stem("patate"));
+        assert_eq!(stem("dato"), "dat");
+    }
+
+    #[test]
+    fn salted_water_uses_the_salt() {
+        let mut pasta = recipe("time: 10m\ningredients: [Pasta 100 g, Sale]");
+        pasta.preparazione = vec![serde_yaml::from_str(
+            "Mettere sul fornello la pentola con l'acqua salata e cuocere la pasta.",
+        )
+        .unwrap()];
+        let warnings: Vec<_> = lint(&book([("pasta", pasta)]))
+            .into_iter()
+            .map(|warning| (warning.kind, warning.subject))
+            .collect();
+        assert!(
+            !warnings.contains(&(Kind::UnusedIngredient, "Sale".to_owned())),
+            "{warnings:?}"
+        );
+    }
+
+    #[test]
+    fn stems_of_vowels_only_are_the_word() {
+        assert_eq!(stem("aia"), "aia");
+    }
+
+    #[test]
+    fn other_recipes_are_matched_by_whole_names() {
+        let mut bollito = recipe("time: 10m\ningredients: [Salsa verde 100 g]");
+        bollito.preparazione = vec![serde_yaml::from_str("Servire con la salsa verde.").unwrap()];
+        let mut sugo = recipe("time: 10m\ningredients: [Pomodoro 100 g]");
+        sugo.preparazione = vec![serde_yaml::from_str(
+            "Cuocere il pomodoro con la salsa verde e servire con insalata verde.",
+        )
+        .unwrap()];
+        let warnings: Vec<_> = lint(&book([("bollito", bollito), ("sugo", sugo)]))
+            .into_iter()
+            .map(|warning| (warning.kind, warning.subject))
+            .collect();
+        assert_eq!(
+            warnings,
+            [(Kind::UndeclaredIngredient, "salsa verde".to_owned())]
+        );
+    }
+
+    /// The warnings of a recipe with the given header and a single step
+    fn warnings_of(header: &str, step: &str) -> Vec<(Kind, String)> {
+        let mut sugo = recipe(header);
+        sugo.preparazione = vec![serde_yaml::from_str(step).unwrap()];
+        lint(&book([("sugo", sugo)]))
+            .into_iter()
+            .map(|warning| (warning.kind, warning.subject))
+            .collect()
+    }
+
+    #[test]
+    fn tools_are_checked_against_the_steps() {
+        let warnings = warnings_of(
+            "time: 10m\ningredients: [Pomodoro 100 g]\ntools: [Pentola, Mestolo]",
+            "Cuocere il pomodoro nella pentola e scolarlo nel colino.",
+        );
+        assert_eq!(
+            warnings,
+            [
+                (Kind::UnusedTool, "Mestolo".to_owned()),
+                (Kind::UndeclaredTool, "colino".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn allowed_warnings_are_not_reported() {
+        let warnings = warnings_of(
+            "time: 10m\ningredients: [Pomodoro 100 g]\ntools: [Pentola, Mestolo]\n\
+             allow: {unused-tool: [mestolo], undeclared-tool: [Colino]}",
+            "Cuocere il pomodoro nella pentola e scolarlo nel colino.",
+        );
+        assert_eq!(warnings, []);
+    }
+}
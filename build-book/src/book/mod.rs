@@ -1,37 +1,329 @@
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::fs::{create_dir_all, read_dir, read_to_string};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
 use chrono::{DateTime, Utc};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::diagnostic::{Errors, InFile};
+
+pub mod assets;
+
+pub mod cache;
+use self::cache::BuildCache;
+
+pub mod format;
+
 pub mod front_matter;
 use self::front_matter::FrontMatter;
 
 pub mod introduction;
-use self::introduction::Introduction;
+use self::introduction::{Introduction, IntroductionTranslation};
+
+pub mod language;
+use self::language::Language;
+
+pub mod links;
+
+pub mod lint;
 
 pub mod recipe;
 use self::recipe::Recipe;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+pub mod schema;
+
+pub mod shopping;
+
+pub mod subrecipe;
+
+pub mod tags;
+use self::tags::Tags;
+
+pub mod translation;
+use self::translation::Translation;
+
+pub mod watch;
+
+/// The source file of the recipe `name` in the book at `root`, or of its translation
+/// in `language`
+#[must_use]
+pub fn recipe_file(root: &Path, name: &str, language: Option<Language>) -> PathBuf {
+    match language {
+        Some(language) => root.join(format!("recipes/{name}.{language}.md")),
+        None => root.join(format!("recipes/{name}.md")),
+    }
+}
+
+/// Whether the recipe `name` is missing from the book, as opposed to having failed to
+/// load, that is already reported on its own
+#[must_use]
+pub fn is_unknown_recipe(
+    recipes: &BTreeMap<String, Recipe>,
+    root: Option<&Path>,
+    name: &str,
+) -> bool {
+    let failed = || {
+        root.is_some_and(|root| recipe_file(root, name, None).is_file())
+            && translation::split_name(name).1.is_none()
+    };
+    !recipes.contains_key(name) && !failed()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Book {
     pub front_matter: FrontMatter,
     pub introduction: Introduction,
     pub recipes: BTreeMap<String, Recipe>,
+    /// Vocabulary of the tags, if the book declares one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Tags>,
+    /// Translations of the book in other languages
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<Language, Translation>,
+    /// Directory the book was loaded from, where its images are found. Missing for
+    /// the books imported from yaml
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
 }
 impl Book {
     pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::load_cached(path, &mut BuildCache::disabled())
+    }
+
+    /// Load the book, reusing the recipes parsed by a previous build and updating the
+    /// cache with the new ones
+    pub fn load_cached(path: impl AsRef<Path>, cache: &mut BuildCache) -> anyhow::Result<Self> {
         log::info!("Loading book from {}", path.as_ref().display());
-        Ok(Self {
-            front_matter: FrontMatter::load(path.as_ref().join("front_matter.yml"))
-                .context("While loading `front_matter.yml`")?,
-            introduction: Introduction::load(path.as_ref().join("introduction"))
-                .context("While loading `introduction`")?,
-            recipes: Recipe::load_dir(path.as_ref().join("recipes"))
-                .context("While loading `recipes`")?,
-        })
+        let mut errors = Errors::default();
+        let front_matter =
+            errors.collect(FrontMatter::load(path.as_ref().join("front_matter.yml")));
+        let introduction = errors.collect(Introduction::load(path.as_ref().join("introduction")));
+        // an invalid front matter is already reported, the recipes are still checked
+        let language = front_matter
+            .as_ref()
+            .map_or_else(Language::default, |front_matter| front_matter.language);
+        let mut recipes =
+            Recipe::load_dir(path.as_ref().join("recipes"), language, cache, &mut errors);
+        cache.set_recipes(&recipes, language);
+        let translated_recipes = errors.collect(translation::take_recipes(&mut recipes, language));
+        let translated_introduction = errors.collect(IntroductionTranslation::load_all(
+            path.as_ref().join("introduction"),
+            language,
+        ));
+        let tags = errors.collect(Tags::load(path.as_ref().join("tags.yml")));
+
+        let (Some(front_matter), Some(introduction)) = (front_matter, introduction) else {
+            // without them there is no book, but the recipes that loaded are still checked
+            if let Some(Some(tags)) = &tags {
+                errors.collect(tags.check(&recipes, Some(path.as_ref())));
+            }
+            errors.collect(subrecipe::check(&recipes, Some(path.as_ref())));
+            return Err(errors.into());
+        };
+        let book = Self {
+            front_matter,
+            introduction,
+            recipes,
+            tags: tags.flatten(),
+            translations: translation::gather(
+                translated_introduction.unwrap_or_default(),
+                translated_recipes.unwrap_or_default(),
+            ),
+            root: Some(path.as_ref().to_owned()),
+        };
+        // the translations were checked while taking them apart from the recipes
+        errors.collect(book.check_references());
+        errors.into_result(book)
+    }
+
+    /// Load a book written by the yaml frontend, checking it is a valid book
+    pub fn load_yaml(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        log::info!("Loading yaml book from {}", path.as_ref().display());
+        let path = path.as_ref();
+        let source = read_to_string(path)
+            .context("Cannot read file")
+            .in_file(path, None)?;
+        let book: Self = serde_yaml::from_str(&source)
+            .context("Cannot parse book")
+            .in_file(path, Some(&source))?;
+
+        let mut errors = Errors::default();
+        for name in book.recipes.keys() {
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                errors.push(anyhow!(
+                    "`{name}` is not a valid recipe name, as it must be usable as a file name"
+                ))
+            } else if translation::split_name(name).1.is_some() {
+                errors.push(anyhow!(
+                    "`{name}` is not a valid recipe name, as its file would be read as a translation"
+                ))
+            }
+        }
+        errors.collect(book.check());
+        errors.into_result(book)
+    }
+
+    /// Check the references between the parts of the book, like the tags used by the
+    /// recipes and the recipes used as ingredients, and its translations
+    pub fn check(&self) -> anyhow::Result<()> {
+        let mut errors = Errors::default();
+        errors.collect(self.check_references());
+        errors.collect(translation::check(self));
+        errors.into_result(())
+    }
+
+    /// Check the tags, the recipes used as ingredients, the links and the images
+    fn check_references(&self) -> anyhow::Result<()> {
+        let mut errors = Errors::default();
+        if let Some(tags) = &self.tags {
+            errors.collect(tags.check(&self.recipes, self.root.as_deref()));
+        }
+        errors.collect(subrecipe::check(&self.recipes, self.root.as_deref()));
+        errors.collect(links::check(self));
+        errors.collect(assets::check(self));
+        errors.into_result(())
+    }
+
+    /// Write the book as a source directory, that can be loaded back with [`Book::load`]
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        log::info!("Saving book to {}", path.as_ref().display());
+        let recipes = path.as_ref().join("recipes");
+        create_dir_all(&recipes).context("Cannot create recipes directory")?;
+
+        self.front_matter
+            .save(path.as_ref().join("front_matter.yml"))
+            .context("While saving `front_matter.yml`")?;
+        self.introduction
+            .save(path.as_ref().join("introduction"))
+            .context("While saving `introduction`")?;
+        for (name, recipe) in &self.recipes {
+            recipe
+                .save(
+                    recipes.join(format!("{name}.md")),
+                    self.front_matter.language,
+                )
+                .with_context(|| format!("While saving recipe {name}"))?;
+        }
+        assets::copy(self, path.as_ref()).context("While copying the images")?;
+        for (language, translation) in &self.translations {
+            translation
+                .introduction
+                .save(path.as_ref().join("introduction"), *language)
+                .with_context(|| format!("While saving the `{language}` introduction"))?;
+            for (name, recipe) in &translation.recipes {
+                recipe
+                    .save(recipes.join(format!("{name}.{language}.md")), *language)
+                    .with_context(|| format!("While saving recipe {name} in `{language}`"))?;
+            }
+        }
+        if let Some(tags) = &self.tags {
+            tags.save(path.as_ref().join("tags.yml"))
+                .context("While saving `tags.yml`")?;
+        }
+        Ok(())
+    }
+
+    /// Regenerate the source directory at `path` from the content of the book
+    ///
+    /// Nothing is written if the directory contains recipes that are not in the book,
+    /// as they would be loaded back together with it. After writing, the sources are
+    /// loaded again to check they give back the same book.
+    pub fn regenerate(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let recipes = path.join("recipes");
+        if recipes.is_dir() {
+            let files: Vec<PathBuf> = read_dir(&recipes)
+                .and_then(|entries| entries.map(|entry| entry.map(|e| e.path())).try_collect())
+                .context("Cannot read recipes directory")?;
+            let stale: Vec<_> = files
+                .iter()
+                .filter(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "md"))
+                .filter_map(|file| file.file_stem()?.to_str())
+                .filter(|name| !self.has_recipe_file(name))
+                .map(|name| format!("`{name}`"))
+                .collect();
+            if !stale.is_empty() {
+                bail!(
+                    "The recipes {} are not in the book, remove them before writing to {}",
+                    stale.join(", "),
+                    path.display()
+                )
+            }
+        }
+
+        self.save(path)?;
+
+        log::info!("Checking that the written book loads back the same");
+        let written = Book::load(path)?;
+        if !self.equivalent(&written) {
+            bail!("The written book does not load back to the same content")
+        }
+        Ok(())
+    }
+
+    /// Check if the recipe file `name.md` belongs to the book, as a recipe or a translation
+    fn has_recipe_file(&self, name: &str) -> bool {
+        match translation::split_name(name) {
+            (original, Some(language)) => self
+                .translations
+                .get(&language)
+                .is_some_and(|translation| translation.recipes.contains_key(original)),
+            (name, None) => self.recipes.contains_key(name),
+        }
+    }
+
+    /// Check if two books have the same content, ignoring when their files were modified
+    #[must_use]
+    pub fn equivalent(&self, other: &Book) -> bool {
+        fn content(book: &Book) -> Option<serde_yaml::Value> {
+            let mut book = book.clone();
+            book.front_matter.modified = None;
+            book.introduction.zen.metas.modified = None;
+            book.introduction.prologue.metas.modified = None;
+            book.introduction.warnings.metas.modified = None;
+            book.introduction.thanks.metas.modified = None;
+            for recipe in book.recipes.values_mut() {
+                recipe.modified = None;
+            }
+            for translation in book.translations.values_mut() {
+                let introduction = &mut translation.introduction;
+                for metas in [
+                    introduction.zen.as_mut().map(|page| &mut page.metas),
+                    introduction.prologue.as_mut().map(|page| &mut page.metas),
+                    introduction.warnings.as_mut().map(|page| &mut page.metas),
+                    introduction.thanks.as_mut().map(|page| &mut page.metas),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    metas.modified = None;
+                }
+                for recipe in translation.recipes.values_mut() {
+                    recipe.modified = None;
+                }
+            }
+            serde_yaml::to_value(book).ok()
+        }
+        match (content(self), content(other)) {
+            (Some(this), Some(other)) => this == other,
+            _ => false,
+        }
+    }
+
+    /// Scale all the recipes to yield `servings` servings
+    pub fn scale(&mut self, servings: NonZeroU32) {
+        for recipe in self.recipes.values_mut() {
+            recipe.scale(servings)
+        }
+        for translation in self.translations.values_mut() {
+            for recipe in translation.recipes.values_mut() {
+                recipe.scale(servings)
+            }
+        }
     }
 
     #[must_use]
@@ -45,3 +337,128 @@ impl Book {
         Some(max)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::parsers::markdown::parse_headed;
+
+    /// The parts of a book that are not recipes
+    const EMPTY_BOOK: &str = r"
+front_matter:
+  title: Ricette
+  subtitle: Di prova
+  author: Test
+  email: test@example.com
+  site: https://example.com
+introduction:
+  zen: { metas: { title: Zen }, content: { items: [] } }
+  prologue: { metas: { title: Prologo }, content: Prologo }
+  warnings: { metas: { title: Avvisi }, content: { items: [] } }
+  thanks: { metas: { title: Grazie }, content: Grazie }
+recipes: {}
+";
+
+    /// A recipe with the given yaml keys in the header, besides the name, and the tools
+    /// and the tags if they are not given
+    pub(crate) fn recipe(header: &str) -> Recipe {
+        let mut header = header.to_owned();
+        for key in ["tools", "tags"] {
+            if !header.contains(&format!("{key}:")) {
+                header.push_str(&format!("\n{key}: []"))
+            }
+        }
+        let source = format!(
+            "---\nname: Prova\n{header}\n---\nDescrizione.\n\n# Preparazione\n1. Cucinare.\n"
+        );
+        Recipe::from_md(parse_headed(&source), Language::default()).expect("valid recipe")
+    }
+
+    /// A directory holding the sources of a book, removed when dropped
+    pub(crate) struct TempBook(pub PathBuf);
+    impl TempBook {
+        /// Save `book` in a new temporary directory, named after the test using it
+        pub(crate) fn new(book: &Book, test: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("build-book-{test}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            book.save(&path).expect("the book should be saved");
+            Self(path)
+        }
+    }
+    impl Drop for TempBook {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A book with only the given recipes
+    pub(crate) fn book<'r>(recipes: impl IntoIterator<Item = (&'r str, Recipe)>) -> Book {
+        let mut book: Book = serde_yaml::from_str(EMPTY_BOOK).expect("valid book");
+        book.recipes = recipes
+            .into_iter()
+            .map(|(name, recipe)| (name.to_owned(), recipe))
+            .collect();
+        book
+    }
+
+    #[test]
+    fn equivalent_ignores_modified() {
+        let original = book([("sale", recipe("time: 10m\ningredients: [Sale]"))]);
+        let mut modified = original.clone();
+        for recipe in modified.recipes.values_mut() {
+            recipe.modified = Some(Utc::now())
+        }
+        let other = book([("pepe", recipe("time: 10m\ningredients: [Pepe]"))]);
+        assert!(original.equivalent(&modified));
+        assert!(!original.equivalent(&other));
+    }
+
+    #[test]
+    fn load_reports_every_error() {
+        let sources = TempBook::new(
+            &book([
+                (
+                    "sugo",
+                    recipe("time: 10m\ningredients: ['[[rotta]] 1', Sale]"),
+                ),
+                ("pasta", recipe("time: 10m\ningredients: ['[[manca]] 1']")),
+            ]),
+            "load-errors",
+        );
+        std::fs::write(
+            sources.0.join("recipes/rotta.md"),
+            "---\nname: Rotta\ntime: 10x\n---\n",
+        )
+        .unwrap();
+
+        let errors = Book::load(&sources.0)
+            .expect_err("the book has errors")
+            .downcast::<Errors>()
+            .expect("the errors are collected");
+        let messages: Vec<_> = errors.0.iter().map(|err| format!("{err:#}")).collect();
+        // the recipe that did not load is not reported again where it is used
+        assert_eq!(messages.len(), 2, "{messages:#?}");
+        assert!(messages[0].contains("rotta.md:3"), "{}", messages[0]);
+        assert!(
+            messages[1].contains("unknown recipe `manca`"),
+            "{}",
+            messages[1]
+        );
+    }
+
+    #[test]
+    fn regenerate_keeps_the_recipes_out_of_the_book() {
+        let sources = TempBook::new(
+            &book([("sugo", recipe("time: 10m\ningredients: [Sale]"))]),
+            "regenerate-stale",
+        );
+        let stale = sources.0.join("recipes/vecchio.md");
+        std::fs::write(&stale, "---\nname: Vecchio\n---\n").unwrap();
+
+        let err = book([]).regenerate(&sources.0).unwrap_err();
+        assert!(err.to_string().contains("`vecchio`"), "{err}");
+        assert!(stale.exists());
+        assert!(sources.0.join("recipes/sugo.md").exists());
+    }
+}
@@ -0,0 +1,154 @@
+//! Details describing a recipe, that do not change how it is made
+
+use std::str::FromStr;
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use crate::parsers::string_or_struct;
+
+/// How hard a recipe is to make
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Difficulty {
+    #[serde(alias = "facile")]
+    Easy,
+    #[serde(alias = "media")]
+    Medium,
+    #[serde(alias = "difficile")]
+    Hard,
+}
+
+/// A month of the year, to tell when a recipe is in season
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Month {
+    #[serde(alias = "gennaio")]
+    January,
+    #[serde(alias = "febbraio")]
+    February,
+    #[serde(alias = "marzo")]
+    March,
+    #[serde(alias = "aprile")]
+    April,
+    #[serde(alias = "maggio")]
+    May,
+    #[serde(alias = "giugno")]
+    June,
+    #[serde(alias = "luglio")]
+    July,
+    #[serde(alias = "agosto")]
+    August,
+    #[serde(alias = "settembre")]
+    September,
+    #[serde(alias = "ottobre")]
+    October,
+    #[serde(alias = "novembre")]
+    November,
+    #[serde(alias = "dicembre")]
+    December,
+}
+
+/// Where a recipe comes from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    /// Who or what the recipe was taken from, like a person or a book
+    pub text: String,
+    /// Where the original recipe can be found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+}
+/// A source can be given as its text alone
+impl FromStr for Source {
+    type Err = !;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            text: s.to_owned(),
+            url: None,
+        })
+    }
+}
+
+/// Without an url, the source is written as its text alone
+impl Serialize for Source {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Fields<'s> {
+            text: &'s str,
+            url: &'s Url,
+        }
+        match &self.url {
+            Some(url) => Fields {
+                text: &self.text,
+                url,
+            }
+            .serialize(serializer),
+            None => serializer.serialize_str(&self.text),
+        }
+    }
+}
+
+/// Deserialize an optional source, given as its text or with all the fields
+pub fn deserialize_source<'de, D>(deserializer: D) -> Result<Option<Source>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_or_struct::string_or_struct(deserializer).map(Some)
+}
+
+/// Schema of the source in the book model
+pub fn source_schema(gen: &mut SchemaGenerator) -> Schema {
+    string_or_struct::schema::<Source>(gen, "Who or what the recipe was taken from")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::book::tests::recipe;
+
+    #[test]
+    fn details_are_read_in_italian_too() {
+        let sugo = recipe(
+            "time: 10m\ningredients: [Sale]\ndifficulty: media\nseason: [ottobre, july]\n\
+             source: La nonna\nimage: foto/sugo.jpg\ncalorie: 300",
+        );
+        assert_eq!(sugo.difficulty, Some(Difficulty::Medium));
+        assert_eq!(
+            sugo.season.into_iter().collect::<Vec<_>>(),
+            [Month::July, Month::October]
+        );
+        assert_eq!(sugo.source.unwrap().text, "La nonna");
+        assert_eq!(sugo.image.unwrap().to_str(), Some("foto/sugo.jpg"));
+        assert_eq!(sugo.unknown_keys, ["calorie"]);
+    }
+
+    #[test]
+    fn sources_without_url_are_their_text() {
+        let Ok(source) = "La nonna".parse::<Source>();
+        assert_eq!(serde_json::to_value(&source).unwrap(), json!("La nonna"));
+
+        let source = Source {
+            url: Some("https://example.com/sugo".parse().unwrap()),
+            ..source
+        };
+        let value = serde_json::to_value(&source).unwrap();
+        assert_eq!(
+            value,
+            json!({"text": "La nonna", "url": "https://example.com/sugo"})
+        );
+        let header = format!("time: 10m\ningredients: [Sale]\nsource: {value}");
+        assert_eq!(recipe(&header).source, Some(source));
+    }
+}
@@ -1,9 +1,11 @@
 use std::{
-    collections::BTreeMap,
-    fs::{read_dir, File},
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    fs::{read_dir, write, File},
     io::read_to_string,
     mem,
-    path::Path,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -11,102 +13,333 @@ use anyhow::{bail, Context};
 use chrono::{DateTime, Duration, Utc};
 use lazy_regex::regex_captures;
 use markdown::{
-    mdast::{Heading, List, Node, Root, Text},
-    Constructs, ParseOptions,
+    mdast::{Heading, List, ListItem, Node, Root, Text},
+    unist::Position,
 };
-use serde::{Deserialize, Serialize};
+use rayon::prelude::*;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{RootSchema, Schema},
+    schema_for, JsonSchema,
+};
+use serde::{Deserialize, Serialize, Serializer};
+
+use super::{
+    cache::BuildCache,
+    language::{Language, Section},
+    links, lint, translation,
+};
+use crate::{
+    diagnostic::{Errors, InFile, Located},
+    parsers::{
+        headed_md::HeadedMarkdown,
+        markdown::{parse_headed, stringify, Markdown},
+        string_or_struct, DisplayMd, FromMd,
+    },
+};
+
+pub mod details;
+use self::details::{Difficulty, Month, Source};
 
-use crate::parsers::{headed_md::HeadedMarkdown, markdown::Markdown, FromMd};
+pub mod step;
+use self::step::Step;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+pub mod times;
+use self::times::Times;
+
+pub mod unit;
+use self::unit::Unit;
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Recipe {
     pub name: Markdown,
-    #[serde(with = "crate::parsers::humantime_duration")]
-    pub time: Duration,
+    /// Read also from a single duration, that is the total time
+    #[serde(deserialize_with = "crate::parsers::string_or_struct::string_or_struct")]
+    #[schemars(schema_with = "times::schema")]
+    pub time: Times,
+    #[serde(default = "one_serving", alias = "serves")]
+    pub servings: NonZeroU32,
     pub ingredients: Vec<Ingredient>,
     pub tools: Vec<Markdown>,
     pub tags: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    /// Months when the recipe is in season
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub season: BTreeSet<Month>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "details::deserialize_source"
+    )]
+    #[schemars(schema_with = "details::source_schema")]
+    pub source: Option<Source>,
+    /// Picture of the dish, as a path relative to the recipe file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<PathBuf>,
+    /// Lint warnings silenced for this recipe, by kind
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub allow: BTreeMap<lint::Kind, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub modified: Option<DateTime<Utc>>,
+    /// Keys of the header that are not recognized and were ignored, reported by the lints
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
+    /// Headings that are not sections of the recipes, whose content was ignored,
+    /// reported by the lints
+    #[serde(skip)]
+    pub unknown_headings: Vec<String>,
 
     pub descr: Markdown,
-    pub preparazione: Vec<Markdown>,
+    pub preparazione: Vec<Step>,
     pub modifiche_e_aggiunte: Vec<Markdown>,
 }
 impl Recipe {
-    pub fn load_dir(path: impl AsRef<Path>) -> anyhow::Result<BTreeMap<String, Self>> {
+    /// Load all the recipes in `path`, reusing the ones in `cache` whose source was not
+    /// modified
+    ///
+    /// The recipes that cannot be loaded are reported in `errors` and left out, so the
+    /// checks of the book still run on the others.
+    pub fn load_dir(
+        path: impl AsRef<Path>,
+        language: Language,
+        cache: &BuildCache,
+        errors: &mut Errors,
+    ) -> BTreeMap<String, Self> {
         log::info!("Loading recipes from {}", path.as_ref().display());
 
+        let path = path.as_ref();
         let mut recipes = BTreeMap::new();
 
-        for f in read_dir(path).context("Cannot read dir")? {
-            let f = f.context("Cannot read directory entry")?;
+        let Some(dir) = errors.collect(
+            read_dir(path)
+                .context("Cannot read dir")
+                .in_file(path, None),
+        ) else {
+            return recipes;
+        };
+        let mut files = vec![];
+        for f in dir {
+            let Some(f) =
+                errors.collect(f.context("Cannot read directory entry").in_file(path, None))
+            else {
+                continue;
+            };
             if !f.file_type().is_ok_and(|f| f.is_file()) {
                 continue; // ignore all subdirectories
             }
             let Some(name) = f.file_name().to_string_lossy().into_owned().strip_suffix(".md").map(ToOwned::to_owned) else {continue; /* ignore all files not ending in .md */};
-            let recipe = Self::load(f.path())
-                .map_err(|err| err.context(format!("Error in parsing recipe {name}")))?;
-            recipes.insert(name, recipe);
+            files.push((name, f));
         }
+        // sorting so the errors are always reported in the same order
+        files.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
 
-        Ok(recipes)
+        let loaded: Vec<_> = files
+            .into_par_iter()
+            .map(|(name, f)| {
+                let cached = f
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| cache.recipe(&name, modified.into(), language));
+                if let Some(recipe) = cached {
+                    log::debug!("Using cached recipe {name}");
+                    (name, Ok(recipe))
+                } else {
+                    // translations are written with the headings of their language
+                    let language = translation::split_name(&name).1.unwrap_or(language);
+                    (name, Self::load(f.path(), language))
+                }
+            })
+            .collect();
+        for (name, recipe) in loaded {
+            if let Some(recipe) = errors.collect(recipe) {
+                recipes.insert(name, recipe);
+            }
+        }
+
+        recipes
     }
-    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    pub fn load(path: impl AsRef<Path>, language: Language) -> anyhow::Result<Self> {
         log::info!("Loading recipe from {}", path.as_ref().display());
+        let path = path.as_ref();
 
-        let f = File::open(path.as_ref()).context("Cannot open file")?;
-
-        let HeadedMarkdown::<HumanHeader, HumanContent> { metas, content } = FromMd::parse(
-            markdown::to_mdast(
-                &read_to_string(&f).context("Cannot read file")?,
-                &ParseOptions {
-                    constructs: Constructs {
-                        frontmatter: true,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-            )
-            .expect("Simple markdown should not panic"),
-        )
-        .context("While parsing")?;
+        let f = File::open(path)
+            .context("Cannot open file")
+            .in_file(path, None)?;
+        let source = read_to_string(&f)
+            .context("Cannot read file")
+            .in_file(path, None)?;
+
+        let recipe = Self::from_md(parse_headed(&source), language)
+            .context("While parsing")
+            .in_file(path, Some(&source))?;
 
         let modified = f
             .metadata()
             .and_then(|m| m.modified())
             .inspect_err(|err| {
-                log::warn!(
-                    "Cannot read the modified time of {}: {err}",
-                    path.as_ref().display()
-                )
+                log::warn!("Cannot read the modified time of {}: {err}", path.display())
             })
             .ok()
             .map(Into::into);
 
+        Ok(Self { modified, ..recipe })
+    }
+    /// Write the recipe as a markdown source file
+    pub fn save(&self, path: impl AsRef<Path>, language: Language) -> anyhow::Result<()> {
+        log::info!("Saving recipe to {}", path.as_ref().display());
+        let md = self.to_md(language).context("While formatting")?;
+        write(path, stringify(&md) + "\n").context("Cannot write file")
+    }
+
+    #[must_use]
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    /// Schema of the yaml header of the recipe files
+    #[must_use]
+    pub fn header_schema() -> RootSchema {
+        schema_for!(HumanHeader)
+    }
+
+    /// Check if a lint warning was silenced for this recipe
+    #[must_use]
+    pub fn allows(&self, kind: lint::Kind, subject: &str) -> bool {
+        self.allow.get(&kind).is_some_and(|subjects| {
+            subjects
+                .iter()
+                .any(|allowed| allowed.trim().to_lowercase() == subject.trim().to_lowercase())
+        })
+    }
+
+    /// All the markdown texts of the recipe, that can contain links
+    #[must_use]
+    pub fn markdowns(&self) -> Vec<&Markdown> {
+        let mut mds = vec![&self.name, &self.descr];
+        for ingredient in &self.ingredients {
+            mds.push(&ingredient.name);
+            mds.extend(&ingredient.comment);
+        }
+        mds.extend(&self.tools);
+        mds.extend(self.preparazione.iter().map(|step| &step.text));
+        mds.extend(&self.modifiche_e_aggiunte);
+        mds
+    }
+
+    pub fn markdowns_mut(&mut self) -> Vec<&mut Markdown> {
+        let mut mds = vec![&mut self.name, &mut self.descr];
+        for ingredient in &mut self.ingredients {
+            mds.push(&mut ingredient.name);
+            mds.extend(&mut ingredient.comment);
+        }
+        mds.extend(&mut self.tools);
+        mds.extend(self.preparazione.iter_mut().map(|step| &mut step.text));
+        mds.extend(&mut self.modifiche_e_aggiunte);
+        mds
+    }
+
+    /// Scale all the quantities of the recipe so it yields `servings` servings
+    pub fn scale(&mut self, servings: NonZeroU32) {
+        let factor = f64::from(servings.get()) / f64::from(self.servings.get());
+        for ingredient in &mut self.ingredients {
+            ingredient.quantity.scale(factor)
+        }
+        self.servings = servings;
+    }
+}
+
+impl Recipe {
+    /// Parse a recipe source, recognizing the section headings of `language`
+    pub fn from_md(md: Node, language: Language) -> anyhow::Result<Self> {
+        let HeadedMarkdown::<HumanHeader, Markdown> { metas, content } = FromMd::parse(md)?;
+        let content = HumanContent::from_md(content.0, language).context("Cannot parse content")?;
         Ok(Self {
             name: metas.name,
-            time: metas.time,
+            time: Times::new(metas.prep, metas.cook, metas.rest, metas.time)
+                .context("Invalid times")?,
+            servings: metas.servings,
             ingredients: metas.ingredients.into_iter().map(Into::into).collect(),
             tools: metas.tools,
             tags: metas.tags,
-            modified,
+            difficulty: metas.difficulty,
+            season: metas.season,
+            source: metas.source,
+            image: metas.image,
+            allow: metas.allow,
+            modified: None,
+            unknown_keys: metas.unknown.into_keys().collect(),
+            unknown_headings: content.unknown_headings,
             descr: content.descr,
-            preparazione: content.preparazione,
+            preparazione: content.preparazione.into_iter().map(Step::new).collect(),
             modifiche_e_aggiunte: content.modifiche_e_aggiunte,
         })
     }
-    #[must_use]
-    pub fn modified(&self) -> Option<DateTime<Utc>> {
-        self.modified
+
+    /// Write the recipe as a markdown source, with the section headings of `language`
+    pub fn to_md(&self, language: Language) -> anyhow::Result<Node> {
+        HeadedMarkdown {
+            metas: HumanHeader {
+                name: self.name.clone(),
+                time: self.time.explicit_total(),
+                prep: self.time.prep(),
+                cook: self.time.cook(),
+                rest: self.time.rest(),
+                servings: self.servings,
+                ingredients: self
+                    .ingredients
+                    .iter()
+                    .cloned()
+                    .map(HumanIngredient)
+                    .collect(),
+                tools: self.tools.clone(),
+                tags: self.tags.clone(),
+                difficulty: self.difficulty,
+                season: self.season.clone(),
+                source: self.source.clone(),
+                image: self.image.clone(),
+                allow: self.allow.clone(),
+                unknown: BTreeMap::new(),
+            },
+            content: Markdown(
+                HumanContent {
+                    descr: self.descr.clone(),
+                    preparazione: self
+                        .preparazione
+                        .iter()
+                        .map(|step| step.text.clone())
+                        .collect(),
+                    modifiche_e_aggiunte: self.modifiche_e_aggiunte.clone(),
+                    unknown_headings: vec![],
+                }
+                .to_md(language)?,
+            ),
+        }
+        .fmt()
+    }
+}
+/// A recipe in the default language of the books
+impl FromMd for Recipe {
+    fn parse(md: Node) -> anyhow::Result<Self> {
+        Self::from_md(md, Language::default())
+    }
+}
+impl DisplayMd for Recipe {
+    fn fmt(&self) -> anyhow::Result<Node> {
+        self.to_md(Language::default())
     }
 }
 
+fn one_serving() -> NonZeroU32 {
+    NonZeroU32::MIN
+}
+
 fn is_false(b: &bool) -> bool {
     !b
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Ingredient {
     name: Markdown,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -116,11 +349,69 @@ pub struct Ingredient {
     #[serde(default, skip_serializing_if = "is_false")]
     optional: bool,
 }
+impl Ingredient {
+    #[must_use]
+    pub fn name(&self) -> &Markdown {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn comment(&self) -> Option<&Markdown> {
+        self.comment.as_ref()
+    }
+
+    #[must_use]
+    pub fn quantity(&self) -> &Quantity {
+        &self.quantity
+    }
+
+    #[must_use]
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// The recipe this ingredient stands for, if its name links to one
+    #[must_use]
+    pub fn recipe(&self) -> Option<String> {
+        links::linked(&self.name).into_iter().next()
+    }
+
+    /// Multiply the quantity by `factor`
+    pub fn scale(&mut self, factor: f64) {
+        self.quantity.scale(factor)
+    }
+
+    /// Multiply the quantity by a factor between `min` and `max`
+    pub fn scale_range(&mut self, (min, max): (f64, f64)) {
+        self.quantity.scale_range(min, max)
+    }
+
+    pub fn set_optional(&mut self, optional: bool) {
+        self.optional = optional
+    }
+}
+impl Display for Ingredient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", stringify(&self.name.0))?;
+        if let Some(comment) = &self.comment {
+            write!(f, " ({})", stringify(&comment.0))?;
+        }
+        if self.optional {
+            write!(f, " ?")?;
+        }
+        if !self.quantity.is_to_taste() {
+            write!(f, " {}", self.quantity)?;
+        }
+        Ok(())
+    }
+}
 impl FromStr for Ingredient {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let Some((_,name, comment, opt,q1,q2,unit)) = regex_captures!(r"^\s*(.+?)\s*(?:\(\s*(.+?)\s*\))?\s*(\?)?\s*(?:(\d+(?:\.\d+)?)(?:\s*\-\s*(\d+(?:\.\d+)?))?\s*((?:[[:punct:]]|[[:alpha:]]).*?))?\s*$", s) else {
+        // the comment can follow the name without a space, unless the name ends with a
+        // link like `[Ragù](ragu.md)`
+        let Some((_,name, comment, opt,q1,q2,unit)) = regex_captures!(r"^\s*(.+?)(?:(?:\s+|\b)\(\s*(.+?)\s*\))?\s*(\?)?\s*(?:(\d+(?:\.\d+)?)(?:\s*\-\s*(\d+(?:\.\d+)?))?\s*((?:[[:punct:]]|[[:alpha:]]).*?)?)?\s*$", s) else {
             // bail!("Cannot recognize ingredient format: {}",s);
             unreachable!("The regex should always match")
         };
@@ -142,14 +433,14 @@ impl FromStr for Ingredient {
                 n: a.parse()?,
                 unit: match unit {
                     "" => None,
-                    unit => Some(unit.to_owned()),
+                    unit => Some(unit.into()),
                 },
             },
             (a, b, unit) => Quantity::Range {
                 range: (a.parse()?, b.parse()?),
                 unit: match unit {
                     "" => None,
-                    unit => Some(unit.to_owned()),
+                    unit => Some(unit.into()),
                 },
             },
         };
@@ -162,19 +453,28 @@ impl FromStr for Ingredient {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+/// Quantity of an ingredient
+///
+/// Serialized untagged, as an object with the amount `n` or the `range` and the
+/// optional unit, since YAML would need a tag like `!Exact` to name the variant.
+/// Ingredients to taste leave the quantity out.
+#[derive(Debug, Clone, Deserialize, Serialize, Default, JsonSchema)]
+#[serde(untagged)]
 pub enum Quantity {
+    /// As much as needed, written `q.b.`
     #[default]
     ToTaste,
+    /// A single amount
     Exact {
         n: f64,
         #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit: Option<String>,
+        unit: Option<Unit>,
     },
+    /// An amount between a minimum and a maximum
     Range {
         range: (f64, f64),
         #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit: Option<String>,
+        unit: Option<Unit>,
     },
 }
 
@@ -186,37 +486,218 @@ impl Quantity {
     pub fn is_to_taste(&self) -> bool {
         matches!(self, Self::ToTaste)
     }
+
+    /// Lower and upper bound of the quantity, with its unit
+    fn bounds(&self) -> Option<((f64, f64), Option<&Unit>)> {
+        match self {
+            Quantity::ToTaste => None,
+            Quantity::Exact { n, unit } => Some(((*n, *n), unit.as_ref())),
+            Quantity::Range { range, unit } => Some((*range, unit.as_ref())),
+        }
+    }
+
+    /// Sum two quantities, expressing the result in the unit of `self`
+    ///
+    /// Returns `None` if the quantities cannot be summed, either because one
+    /// of them is to taste or because the units measure different things
+    #[must_use]
+    pub fn checked_add(&self, other: &Quantity) -> Option<Quantity> {
+        let ((a_min, a_max), unit) = self.bounds()?;
+        let ((b_min, b_max), other_unit) = other.bounds()?;
+        let (b_min, b_max) = match (unit, other_unit) {
+            (None, None) => (b_min, b_max),
+            (Some(unit), Some(other_unit)) => (
+                other_unit.convert(b_min, unit)?,
+                other_unit.convert(b_max, unit)?,
+            ),
+            _ => return None,
+        };
+        let unit = unit.cloned();
+        let (min, max) = (a_min + b_min, a_max + b_max);
+        Some(if min == max {
+            Quantity::Exact { n: min, unit }
+        } else {
+            Quantity::Range {
+                range: (min, max),
+                unit,
+            }
+        })
+    }
+
+    /// Multiply the quantity by `factor`. Quantities to taste are left alone.
+    pub fn scale(&mut self, factor: f64) {
+        match self {
+            Quantity::ToTaste => (),
+            Quantity::Exact { n, .. } => *n *= factor,
+            Quantity::Range { range: (a, b), .. } => {
+                *a *= factor;
+                *b *= factor;
+            }
+        }
+    }
+
+    /// Multiply the quantity by a factor between `min` and `max`, turning an exact
+    /// quantity into a range if they differ
+    pub fn scale_range(&mut self, min: f64, max: f64) {
+        if min == max {
+            return self.scale(min);
+        }
+        *self = match std::mem::take(self) {
+            Quantity::ToTaste => Quantity::ToTaste,
+            Quantity::Exact { n, unit } => Quantity::Range {
+                range: (n * min, n * max),
+                unit,
+            },
+            Quantity::Range {
+                range: (a, b),
+                unit,
+            } => Quantity::Range {
+                range: (a * min, b * max),
+                unit,
+            },
+        }
+    }
+}
+impl Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantity::ToTaste => write!(f, "q.b."),
+            Quantity::Exact { n, unit: None } => write!(f, "{}", round(*n)),
+            Quantity::Exact {
+                n,
+                unit: Some(unit),
+            } => write!(f, "{} {}", round(*n), unit.name(round(*n))),
+            Quantity::Range {
+                range: (a, b),
+                unit: None,
+            } => write!(f, "{}-{}", round(*a), round(*b)),
+            Quantity::Range {
+                range: (a, b),
+                unit: Some(unit),
+            } => write!(f, "{}-{} {}", round(*a), round(*b), unit.name(round(*b))),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// Round an amount to two decimals, so scaled quantities read `66.67 g`
+fn round(n: f64) -> f64 {
+    (n * 100.).round() / 100.
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(rename = "RecipeHeader")]
 struct HumanHeader {
+    /// Name of the recipe
     name: Markdown,
-    #[serde(deserialize_with = "crate::parsers::humantime_duration::deserialize")]
-    time: Duration,
+    /// Total time needed to make the recipe, like `25m` or `1h 30m`.
+    /// Can be left out if the other times are given, and is then their sum
+    #[serde(
+        default,
+        alias = "total",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    time: Option<Duration>,
+    /// Time spent working, like chopping and mixing
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    prep: Option<Duration>,
+    /// Time spent cooking
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    cook: Option<Duration>,
+    /// Time the recipe is left alone, like rising or marinating
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    rest: Option<Duration>,
+    /// Number of servings the quantities are for
+    #[serde(default = "one_serving", alias = "serves")]
+    servings: NonZeroU32,
     ingredients: Vec<HumanIngredient>,
     tools: Vec<Markdown>,
     tags: Vec<String>,
+    /// How hard the recipe is to make
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    difficulty: Option<Difficulty>,
+    /// Months when the recipe is in season, like `[june, july, august]`
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    season: BTreeSet<Month>,
+    /// Where the recipe comes from, as a text or with an `url`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "details::deserialize_source"
+    )]
+    #[schemars(schema_with = "details::source_schema")]
+    source: Option<Source>,
+    /// Picture of the dish, as a path relative to the recipe file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<PathBuf>,
+    /// Lint warnings to silence, as the subjects to ignore for each kind of warning
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    allow: BTreeMap<lint::Kind, Vec<String>>,
+    /// Keys that are not recognized, reported by the lints as they would be lost
+    #[serde(flatten, skip_serializing)]
+    #[schemars(skip)]
+    unknown: BTreeMap<String, serde_yaml::Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(transparent)]
 struct HumanIngredient(
-    #[serde(deserialize_with = "crate::parsers::string_or_struct::string_or_struct")] Ingredient,
+    #[serde(deserialize_with = "crate::parsers::string_or_struct::string_or_struct")]
+    #[schemars(schema_with = "ingredient_schema")]
+    Ingredient,
 );
+fn ingredient_schema(gen: &mut SchemaGenerator) -> Schema {
+    string_or_struct::schema::<Ingredient>(
+        gen,
+        "Ingredient written as `name (comment) ? quantity unit`. \
+        The comment, the `?` marking it as optional and the quantity can be left out, \
+        and the quantity can be a range like `30-50 g`. \
+        A name linking to another recipe, like `[[ragu]] 2`, uses that recipe as an ingredient, \
+        and a quantity without unit is the number of its servings that are needed",
+    )
+}
 impl From<HumanIngredient> for Ingredient {
     fn from(value: HumanIngredient) -> Self {
         value.0
     }
 }
+impl Serialize for HumanIngredient {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // always write the short form
+        serializer.collect_str(&self.0)
+    }
+}
 
 #[derive(Debug)]
 struct HumanContent {
     descr: Markdown,
     preparazione: Vec<Markdown>,
     modifiche_e_aggiunte: Vec<Markdown>,
+    /// Headings that are not sections, whose content is ignored
+    unknown_headings: Vec<String>,
 }
-impl FromMd for HumanContent {
-    fn parse(mut md: Node) -> anyhow::Result<Self> {
+impl HumanContent {
+    /// Parse the content of a recipe, recognizing the section headings of `language`
+    fn from_md(mut md: Node, language: Language) -> anyhow::Result<Self> {
         let nodes = mem::take(
             md.children_mut()
                 .context("Content need to split the different paragraphs")?,
@@ -224,30 +705,42 @@ impl FromMd for HumanContent {
 
         let mut descr = vec![];
         let mut preparazione = vec![];
+        let mut preparazione_heading = None;
         let mut modifiche_e_aggiunte = vec![];
+        let mut modifiche_e_aggiunte_heading = None;
+        let mut unknown_headings = vec![];
 
         let mut collecting = Some(&mut descr);
 
         for node in nodes {
             match node {
                 Node::Heading(Heading {
-                    children, depth: 1, ..
+                    children,
+                    depth: 1,
+                    position,
                 }) => match &children[..] {
-                    [Node::Text(Text { value, .. })] if value.trim() == "Preparazione" => {
+                    [Node::Text(Text { value, .. })]
+                        if language.section(value) == Some(Section::Preparation) =>
+                    {
+                        preparazione_heading = position;
                         collecting = Some(&mut preparazione)
                     }
-                    [Node::Text(Text { value, .. })] if value.trim() == "Modifiche e aggiunte" => {
+                    [Node::Text(Text { value, .. })]
+                        if language.section(value) == Some(Section::Variations) =>
+                    {
+                        modifiche_e_aggiunte_heading = position;
                         collecting = Some(&mut modifiche_e_aggiunte)
                     }
                     _ => {
-                        log::warn!(
-                            "Unrecognized header: {}\nThe content will be ignored",
+                        unknown_headings.push(
                             Node::Heading(Heading {
                                 children,
                                 depth: 1,
-                                position: None
+                                position: None,
                             })
                             .to_string()
+                            .trim()
+                            .to_owned(),
                         );
                         collecting = None;
                     }
@@ -267,7 +760,15 @@ impl FromMd for HumanContent {
         .unwrap();
 
         let [Node::List(List{ children:steps, ordered: true, start:Some(1),.. })] = &mut preparazione[..] else {
-            bail!("The `Preparazione` paragraph should be only a ordered list of steps, starting from 1")
+            bail!(locate_section(
+                &md,
+                preparazione_heading,
+                &preparazione,
+                &format!(
+                    "The `{}` paragraph should be only a ordered list of steps, starting from 1",
+                    language.heading(Section::Preparation)
+                )
+            ))
         };
         let preparazione = mem::take(steps)
             .into_iter()
@@ -275,19 +776,231 @@ impl FromMd for HumanContent {
             .try_collect()
             .unwrap();
 
-        let [Node::List(List{ children:steps, ordered: false,.. })] = &mut modifiche_e_aggiunte[..] else {
-            bail!("The `Modifiche e aggiunte` paragraph should be only a unordered list")
+        // the variations are optional, as the recipes without any are written back
+        // without the section
+        let modifiche_e_aggiunte = if modifiche_e_aggiunte_heading.is_some() {
+            let [Node::List(List{ children:steps, ordered: false,.. })] = &mut modifiche_e_aggiunte[..] else {
+                bail!(locate_section(
+                    &md,
+                    modifiche_e_aggiunte_heading,
+                    &modifiche_e_aggiunte,
+                    &format!(
+                        "The `{}` paragraph should be only a unordered list",
+                        language.heading(Section::Variations)
+                    )
+                ))
+            };
+            mem::take(steps)
+                .into_iter()
+                .map(FromMd::parse)
+                .try_collect()
+                .unwrap()
+        } else {
+            vec![]
         };
-        let modifiche_e_aggiunte = mem::take(steps)
-            .into_iter()
-            .map(FromMd::parse)
-            .try_collect()
-            .unwrap();
 
         Ok(Self {
             descr,
             preparazione,
             modifiche_e_aggiunte,
+            unknown_headings,
         })
     }
+
+    /// Write the content of a recipe, with the section headings of `language`
+    fn to_md(&self, language: Language) -> anyhow::Result<Node> {
+        let mut children = match self.descr.fmt().context("While formatting description")? {
+            Node::Root(Root { children, .. }) => children,
+            node => vec![node],
+        };
+
+        children.push(heading(language.heading(Section::Preparation)));
+        children.push(Node::List(List {
+            children: self.preparazione.iter().map(list_item).try_collect()?,
+            position: None,
+            ordered: true,
+            start: Some(1),
+            spread: false,
+        }));
+
+        if !self.modifiche_e_aggiunte.is_empty() {
+            children.push(heading(language.heading(Section::Variations)));
+            children.push(Node::List(List {
+                children: self
+                    .modifiche_e_aggiunte
+                    .iter()
+                    .map(list_item)
+                    .try_collect()?,
+                position: None,
+                ordered: false,
+                start: None,
+                spread: false,
+            }));
+        }
+
+        Ok(Node::Root(Root {
+            children,
+            position: None,
+        }))
+    }
+}
+impl FromMd for HumanContent {
+    fn parse(md: Node) -> anyhow::Result<Self> {
+        Self::from_md(md, Language::default())
+    }
+}
+impl DisplayMd for HumanContent {
+    fn fmt(&self) -> anyhow::Result<Node> {
+        self.to_md(Language::default())
+    }
+}
+
+fn heading(title: &str) -> Node {
+    Node::Heading(Heading {
+        children: vec![Node::Text(Text {
+            value: title.to_owned(),
+            position: None,
+        })],
+        position: None,
+        depth: 1,
+    })
+}
+
+/// Wrap a markdown in a list item, if it is not one already
+fn list_item(md: &Markdown) -> anyhow::Result<Node> {
+    Ok(match md.fmt()? {
+        item @ Node::ListItem(_) => item,
+        Node::Root(Root { children, .. }) => Node::ListItem(ListItem {
+            children,
+            position: None,
+            spread: false,
+            checked: None,
+        }),
+        node => Node::ListItem(ListItem {
+            children: vec![node],
+            position: None,
+            spread: false,
+            checked: None,
+        }),
+    })
+}
+
+/// Locate an error in a section, pointing to its content, its heading, or the whole file
+fn locate_section(
+    root: &Node,
+    heading: Option<Position>,
+    content: &[Node],
+    message: &str,
+) -> Located {
+    match (content.first(), heading) {
+        (Some(node), _) => Located::at(node, message),
+        (None, Some(heading)) => Located::new(heading, message),
+        (None, None) => Located::at(root, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ingredient(s: &str) -> Ingredient {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ingredient_with_everything() {
+        let ingredient = ingredient("Aglio (schiacciato) ? 0.5-1 spicchio");
+        assert_eq!(ingredient.name().0.to_string(), "Aglio");
+        assert_eq!(ingredient.comment().unwrap().0.to_string(), "schiacciato");
+        assert!(ingredient.is_optional());
+        assert_eq!(ingredient.quantity().to_string(), "0.5-1 spicchio");
+    }
+
+    #[test]
+    fn ingredient_to_taste() {
+        let ingredient = ingredient("Sale");
+        assert_eq!(ingredient.name().0.to_string(), "Sale");
+        assert!(ingredient.comment().is_none());
+        assert!(ingredient.quantity().is_to_taste());
+    }
+
+    #[test]
+    fn ingredient_comment_without_space() {
+        let ingredient = ingredient("Pasta(fresca) 100g");
+        assert_eq!(ingredient.name().0.to_string(), "Pasta");
+        assert_eq!(ingredient.comment().unwrap().0.to_string(), "fresca");
+        assert_eq!(ingredient.quantity().to_string(), "100 g");
+    }
+
+    #[test]
+    fn scaled_quantities_are_rounded() {
+        let mut recipe = crate::book::tests::recipe(
+            "time: 10m\nservings: 3\ningredients: [Farina 200 g, Aglio 1-2 spicchio, Uova 2, Sale]",
+        );
+        recipe.scale(NonZeroU32::new(1).unwrap());
+        let quantities: Vec<_> = recipe
+            .ingredients
+            .iter()
+            .map(|ingredient| ingredient.quantity().to_string())
+            .collect();
+        assert_eq!(
+            quantities,
+            ["66.67 g", "0.33-0.67 spicchio", "0.67", "q.b."]
+        );
+        assert_eq!(
+            recipe.ingredients[0].to_string(),
+            "Farina 66.67 g",
+            "the markdown and html output print the same amounts"
+        );
+    }
+
+    #[test]
+    fn ingredient_linking_a_recipe() {
+        let ingredient = ingredient("[Il ragù](ragu.md) 2");
+        assert!(ingredient.comment().is_none());
+        assert_eq!(ingredient.quantity().to_string(), "2");
+        assert_eq!(ingredient.recipe().as_deref(), Some("ragu"));
+
+        let ingredient = self::ingredient("[[ragu]] (avanzato) 1");
+        assert_eq!(ingredient.comment().unwrap().0.to_string(), "avanzato");
+        assert_eq!(ingredient.recipe().as_deref(), Some("ragu"));
+    }
+
+    #[test]
+    fn load_dir_keeps_the_valid_recipes_in_order() {
+        let dir = std::env::temp_dir().join(format!("build-book-load-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("foto")).unwrap();
+        let source = |name: &str, time: &str, heading: &str| {
+            format!("---\nname: {name}\ntime: {time}\ningredients: [Sale]\ntools: []\ntags: []\n---\n\n# {heading}\n1. Cuocere.\n")
+        };
+        for i in 0..20 {
+            let time = if i % 7 == 3 { "10x" } else { "10m" };
+            std::fs::write(
+                dir.join(format!("r{i:02}.md")),
+                source("Ricetta", time, "Preparazione"),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.join("r00.en.md"), source("Recipe", "10m", "Method")).unwrap();
+        std::fs::write(dir.join("note.txt"), "").unwrap();
+
+        let mut errors = Errors::default();
+        let recipes = Recipe::load_dir(
+            &dir,
+            Language::Italian,
+            &BuildCache::disabled(),
+            &mut errors,
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let failed: Vec<_> = errors.0.iter().map(|err| format!("{err:#}")).collect();
+        assert_eq!(failed.len(), 3, "{failed:#?}");
+        for (err, name) in failed.iter().zip(["r03.md", "r10.md", "r17.md"]) {
+            assert!(err.contains(name), "{err}");
+        }
+        assert_eq!(recipes.len(), 18);
+        assert!(recipes.contains_key("r00.en"));
+        assert!(!recipes.contains_key("r03"));
+    }
 }
@@ -0,0 +1,187 @@
+//! Steps of the preparation, with the durations they mention
+
+use chrono::Duration;
+use lazy_regex::{regex, regex_is_match};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::parsers::markdown::Markdown;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Step {
+    pub text: Markdown,
+    /// Durations mentioned in the text, like `cuocere 10 minuti`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub times: Vec<StepTime>,
+}
+impl Step {
+    #[must_use]
+    pub fn new(text: Markdown) -> Self {
+        let times = find_times(&text.0.to_string());
+        Self { text, times }
+    }
+}
+/// The times are always extracted again from the text, so the step can also be
+/// given as its text alone
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(Markdown),
+            Fields { text: Markdown },
+        }
+        let (Repr::Text(text) | Repr::Fields { text }) = Repr::deserialize(deserializer)?;
+        Ok(Self::new(text))
+    }
+}
+
+/// A duration mentioned in a step, possibly as a range like `10-15 minuti`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct StepTime {
+    /// The words the duration was read from
+    pub text: String,
+    #[serde(with = "crate::parsers::humantime_duration")]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::schema")]
+    pub min: Duration,
+    #[serde(with = "crate::parsers::humantime_duration")]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::schema")]
+    pub max: Duration,
+}
+
+/// Find the italian duration phrases in a text
+///
+/// Recognizes numbers in digits or words, ranges like `10-15 minuti` or
+/// `tra 10 e 15 minuti`, halves like `mezz'ora` or `un'ora e mezza`, and
+/// compounds like `un'ora e 20 minuti`. A night counts as 8 hours.
+fn find_times(text: &str) -> Vec<StepTime> {
+    let mut times: Vec<(usize, usize, StepTime)> = vec![];
+    for c in regex!(
+        r"(?i)\b(?:(?:da|tra|fra)\s+(\d+(?:[.,]\d+)?|[[:alpha:]]+['’]?)\s+(?:a|e)\s+(\d+(?:[.,]\d+)?|[[:alpha:]]+['’]?)|(\d+(?:[.,]\d+)?|[[:alpha:]]+['’]?)(?:(?:\s*[-–]\s*|\s+o\s+)(\d+(?:[.,]\d+)?|[[:alpha:]]+['’]?))?)\s*(second[oi]|sec|minut[oi]|min|or[ae]|h|giorn[oi]|nott[ei])\b(?:\s+e\s+(mezz[oa])\b)?"
+    )
+    .captures_iter(text)
+    {
+        let whole = c.get(0).unwrap();
+        let (Some(min), max) = (
+            c.get(1).or(c.get(3)).and_then(|n| number(n.as_str())),
+            c.get(2).or(c.get(4)).map(|n| number(n.as_str())),
+        ) else {
+            continue; // not a number, like in `le ore`
+        };
+        let Some(max) = max.unwrap_or(Some(min)) else {
+            continue;
+        };
+        let unit = match c[5].to_lowercase().as_str() {
+            "secondo" | "secondi" | "sec" => 1.,
+            "minuto" | "minuti" | "min" => 60.,
+            "ora" | "ore" | "h" => 3600.,
+            "giorno" | "giorni" => 86400.,
+            _ => 8. * 3600., // notte
+        };
+        let half = if c.get(6).is_some() { 0.5 } else { 0. };
+        let seconds = |n: f64| Duration::seconds(((n + half) * unit).round() as i64);
+        let time = StepTime {
+            text: whole.as_str().to_owned(),
+            min: seconds(min),
+            max: seconds(max),
+        };
+
+        // `un'ora e 20 minuti` is a single duration
+        if let Some((start, end, last)) = times.last_mut() {
+            if regex_is_match!(r"^\s+e\s+$", &text[*end..whole.start()]) {
+                last.text = text[*start..whole.end()].to_owned();
+                last.min = last.min + time.min;
+                last.max = last.max + time.max;
+                *end = whole.end();
+                continue;
+            }
+        }
+        times.push((whole.start(), whole.end(), time))
+    }
+    times.into_iter().map(|(_, _, time)| time).collect()
+}
+
+/// Read a number written in digits or in words
+fn number(s: &str) -> Option<f64> {
+    let s = s.to_lowercase();
+    let s = s.trim_end_matches(['\'', '’']);
+    if let Ok(n) = s.replace(',', ".").parse() {
+        return Some(n);
+    }
+    Some(match s {
+        "mezz" | "mezzo" | "mezza" => 0.5,
+        "un" | "uno" | "una" => 1.,
+        "due" => 2.,
+        "tre" => 3.,
+        "quattro" => 4.,
+        "cinque" => 5.,
+        "sei" => 6.,
+        "sette" => 7.,
+        "otto" => 8.,
+        "nove" => 9.,
+        "dieci" => 10.,
+        "dodici" => 12.,
+        "quindici" => 15.,
+        "venti" => 20.,
+        "trenta" => 30.,
+        "quaranta" => 40.,
+        "quarantacinque" => 45.,
+        "cinquanta" => 50.,
+        "sessanta" => 60.,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bounds of the durations found in `text`
+    fn times(text: &str) -> Vec<(Duration, Duration)> {
+        find_times(text)
+            .into_iter()
+            .map(|time| (time.min, time.max))
+            .collect()
+    }
+
+    #[test]
+    fn minutes() {
+        let found = find_times("Cuocere per 10 minuti a fuoco basso");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text, "10 minuti");
+        assert_eq!(found[0].min, Duration::minutes(10));
+        assert_eq!(found[0].max, Duration::minutes(10));
+    }
+
+    #[test]
+    fn hour_and_a_half() {
+        assert_eq!(
+            times("Lasciare riposare un'ora e mezza"),
+            [(Duration::minutes(90), Duration::minutes(90))]
+        );
+    }
+
+    #[test]
+    fn range_of_days() {
+        assert_eq!(
+            times("Far maturare 2-3 giorni in frigo"),
+            [(Duration::days(2), Duration::days(3))]
+        );
+    }
+
+    #[test]
+    fn compound_durations_are_summed() {
+        let found = find_times("Cuocere in forno un'ora e 20 minuti");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text, "un'ora e 20 minuti");
+        assert_eq!(found[0].min, Duration::minutes(80));
+    }
+
+    #[test]
+    fn words_that_are_not_numbers() {
+        assert!(times("Servire quando le ore di attesa sono finite").is_empty());
+    }
+}
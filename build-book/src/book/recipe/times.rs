@@ -0,0 +1,220 @@
+//! Times needed to make a recipe
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use chrono::Duration;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use crate::parsers::string_or_struct;
+
+/// How the time of a recipe is spent
+///
+/// Only the total is required, the other times are known only if the recipe gives them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "TimesFields", into = "TimesFields")]
+pub struct Times {
+    prep: Option<Duration>,
+    cook: Option<Duration>,
+    rest: Option<Duration>,
+    /// The total as given by the recipe, even when it is the sum of the others
+    total: Option<Duration>,
+}
+impl Times {
+    /// Collect the times of a recipe, deriving the total from the others if missing
+    ///
+    /// A total shorter than the other times is accepted, the lints report it.
+    pub fn new(
+        prep: Option<Duration>,
+        cook: Option<Duration>,
+        rest: Option<Duration>,
+        total: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        let times = Self {
+            prep,
+            cook,
+            rest,
+            total,
+        };
+        if total.is_none() && times.sum().is_none() {
+            bail!("The recipe needs a time, give at least one of `time`, `prep`, `cook` and `rest`")
+        }
+        Ok(times)
+    }
+
+    /// The sum of the preparation, cooking and resting times, if any is given
+    #[must_use]
+    pub fn sum(&self) -> Option<Duration> {
+        [self.prep, self.cook, self.rest]
+            .into_iter()
+            .flatten()
+            .reduce(|sum, time| sum + time)
+    }
+
+    /// Time spent working, like chopping and mixing
+    #[must_use]
+    pub fn prep(&self) -> Option<Duration> {
+        self.prep
+    }
+
+    /// Time spent cooking
+    #[must_use]
+    pub fn cook(&self) -> Option<Duration> {
+        self.cook
+    }
+
+    /// Time the recipe is left alone, like rising or marinating
+    #[must_use]
+    pub fn rest(&self) -> Option<Duration> {
+        self.rest
+    }
+
+    /// Time from start to finish, the sum of the other times if not given
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.total
+            .or_else(|| self.sum())
+            .expect("a time is always given")
+    }
+
+    /// The total, if it was given and not derived from the other times
+    #[must_use]
+    pub fn explicit_total(&self) -> Option<Duration> {
+        self.total
+    }
+
+    /// Whether the total was given even if it is the sum of the others, that the book
+    /// model cannot tell from a derived one
+    #[must_use]
+    pub fn gives_sum_as_total(&self) -> bool {
+        self.total.is_some() && self.total == self.sum()
+    }
+
+    /// Take the total as given, even if it is the sum of the others
+    pub fn give_total(&mut self) {
+        self.total = Some(self.total())
+    }
+}
+/// A single duration is the total time, as recipes used to have only one
+impl FromStr for Times {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let total = humantime::parse_duration(s).context("Invalid duration")?;
+        Self::new(
+            None,
+            None,
+            None,
+            Some(Duration::from_std(total).context("Duration too big")?),
+        )
+    }
+}
+
+/// The times in the book model, where the total is always written
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Times")]
+struct TimesFields {
+    /// Time spent working, like chopping and mixing
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    prep: Option<Duration>,
+    /// Time spent cooking
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    cook: Option<Duration>,
+    /// Time the recipe is left alone, like rising or marinating
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    rest: Option<Duration>,
+    /// Time from start to finish, the sum of the others if missing
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::parsers::humantime_duration::option"
+    )]
+    #[schemars(schema_with = "crate::parsers::humantime_duration::option::schema")]
+    total: Option<Duration>,
+}
+impl TryFrom<TimesFields> for Times {
+    type Error = anyhow::Error;
+
+    fn try_from(value: TimesFields) -> Result<Self, Self::Error> {
+        let TimesFields {
+            prep,
+            cook,
+            rest,
+            total,
+        } = value;
+        let mut times = Self::new(prep, cook, rest, total)?;
+        // the model always has the total, it is taken as given only if it differs
+        if times.total == times.sum() {
+            times.total = None
+        }
+        Ok(times)
+    }
+}
+impl From<Times> for TimesFields {
+    fn from(times: Times) -> Self {
+        Self {
+            prep: times.prep,
+            cook: times.cook,
+            rest: times.rest,
+            total: Some(times.total()),
+        }
+    }
+}
+
+/// Schema of the times in the book model
+pub fn schema(gen: &mut SchemaGenerator) -> Schema {
+    string_or_struct::schema::<TimesFields>(gen, "The total time, like `25m` or `1h 30m`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(minutes: i64) -> Option<Duration> {
+        Some(Duration::minutes(minutes))
+    }
+
+    #[test]
+    fn model_has_only_the_times() {
+        let times = Times::new(minutes(10), minutes(20), None, minutes(30)).unwrap();
+        assert!(times.gives_sum_as_total());
+        let model = serde_yaml::to_string(&times).unwrap();
+        assert_eq!(model, "prep: 10m\ncook: 20m\ntotal: 30m\n");
+
+        // the total that is the sum of the others is taken as derived
+        let read: Times = serde_yaml::from_str(&model).unwrap();
+        assert_eq!(read.explicit_total(), None);
+        assert_eq!(read.total(), Duration::minutes(30));
+
+        let schema = serde_json::to_string(&schemars::schema_for!(TimesFields)).unwrap();
+        assert!(!schema.contains("total_given"));
+    }
+
+    #[test]
+    fn given_total_is_kept() {
+        let mut times = Times::new(minutes(10), minutes(20), None, None).unwrap();
+        assert_eq!(times.explicit_total(), None);
+        times.give_total();
+        assert_eq!(times.explicit_total(), minutes(30));
+
+        let read: Times = serde_yaml::from_str("prep: 10m\ntotal: 15m").unwrap();
+        assert_eq!(read.explicit_total(), minutes(15));
+    }
+}
@@ -0,0 +1,250 @@
+//! Units of measure for the ingredient quantities
+
+use std::{
+    borrow::Cow,
+    fmt::{self, Display},
+};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+/// What a unit is measuring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    // mass
+    Milligram,
+    Gram,
+    Hectogram,
+    Kilogram,
+    // volume
+    Milliliter,
+    Centiliter,
+    Deciliter,
+    Liter,
+    Teaspoon,
+    Tablespoon,
+    Glass,
+    Cup,
+    // count
+    Piece,
+    // kitchen units without a fixed size
+    Clove,
+    Handful,
+    Pinch,
+    /// A unit that was not recognized, kept as written
+    Unknown(String),
+}
+impl Unit {
+    /// The dimension of the unit, and its size in the base unit of the dimension
+    /// (grams, milliliters or pieces)
+    ///
+    /// Units that cannot be converted into anything else return `None`
+    #[must_use]
+    pub fn base(&self) -> Option<(Dimension, f64)> {
+        Some(match self {
+            Unit::Milligram => (Dimension::Mass, 0.001),
+            Unit::Gram => (Dimension::Mass, 1.),
+            Unit::Hectogram => (Dimension::Mass, 100.),
+            Unit::Kilogram => (Dimension::Mass, 1000.),
+            Unit::Milliliter => (Dimension::Volume, 1.),
+            Unit::Centiliter => (Dimension::Volume, 10.),
+            Unit::Deciliter => (Dimension::Volume, 100.),
+            Unit::Liter => (Dimension::Volume, 1000.),
+            Unit::Teaspoon => (Dimension::Volume, 5.),
+            Unit::Tablespoon => (Dimension::Volume, 15.),
+            Unit::Glass => (Dimension::Volume, 200.),
+            Unit::Cup => (Dimension::Volume, 250.),
+            Unit::Piece => (Dimension::Count, 1.),
+            Unit::Clove | Unit::Handful | Unit::Pinch | Unit::Unknown(_) => return None,
+        })
+    }
+
+    #[must_use]
+    pub fn dimension(&self) -> Option<Dimension> {
+        self.base().map(|(dimension, _)| dimension)
+    }
+
+    /// Convert `n` of this unit into the unit `to`
+    ///
+    /// Returns `None` if the two units do not measure the same dimension
+    #[must_use]
+    pub fn convert(&self, n: f64, to: &Unit) -> Option<f64> {
+        if self == to {
+            return Some(n);
+        }
+        let (from_dim, from_size) = self.base()?;
+        let (to_dim, to_size) = to.base()?;
+        (from_dim == to_dim).then(|| n * from_size / to_size)
+    }
+
+    /// The name of the unit when there are `n` of it, as the written names are plural
+    /// for more than one, like `2 cucchiai`
+    ///
+    /// Symbols and unknown units are the same for any quantity.
+    #[must_use]
+    pub fn name(&self, n: f64) -> Cow<'_, str> {
+        if n <= 1. {
+            return self.to_string().into();
+        }
+        match self {
+            Unit::Teaspoon => "cucchiaini",
+            Unit::Tablespoon => "cucchiai",
+            Unit::Glass => "bicchieri",
+            Unit::Cup => "tazze",
+            Unit::Clove => "spicchi",
+            Unit::Handful => "manciate",
+            Unit::Pinch => "pizzichi",
+            unit => return unit.to_string().into(),
+        }
+        .into()
+    }
+
+    /// Returns `true` if the unit is [`Unknown`].
+    ///
+    /// [`Unknown`]: Unit::Unknown
+    #[must_use]
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Self::Unknown(..))
+    }
+}
+impl From<&str> for Unit {
+    fn from(value: &str) -> Self {
+        let value = value.trim();
+        match value.trim_end_matches('.').to_lowercase().as_str() {
+            "mg" | "milligrammo" | "milligrammi" => Unit::Milligram,
+            "g" | "gr" | "grammo" | "grammi" => Unit::Gram,
+            "hg" | "etto" | "etti" => Unit::Hectogram,
+            "kg" | "chilo" | "chili" | "chilogrammo" | "chilogrammi" => Unit::Kilogram,
+            "ml" | "millilitro" | "millilitri" => Unit::Milliliter,
+            "cl" | "centilitro" | "centilitri" => Unit::Centiliter,
+            "dl" | "decilitro" | "decilitri" => Unit::Deciliter,
+            "l" | "lt" | "litro" | "litri" => Unit::Liter,
+            "cucchiaino" | "cucchiaini" => Unit::Teaspoon,
+            "cucchiaio" | "cucchiai" => Unit::Tablespoon,
+            "bicchiere" | "bicchieri" => Unit::Glass,
+            "tazza" | "tazze" => Unit::Cup,
+            "pz" | "pezzo" | "pezzi" => Unit::Piece,
+            "spicchio" | "spicchi" => Unit::Clove,
+            "manciata" | "manciate" => Unit::Handful,
+            "pizzico" | "pizzichi" => Unit::Pinch,
+            _ => {
+                log::debug!("Unknown unit `{value}`, it will be kept as is");
+                Unit::Unknown(value.to_owned())
+            }
+        }
+    }
+}
+impl Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Unit::Milligram => "mg",
+            Unit::Gram => "g",
+            Unit::Hectogram => "hg",
+            Unit::Kilogram => "kg",
+            Unit::Milliliter => "ml",
+            Unit::Centiliter => "cl",
+            Unit::Deciliter => "dl",
+            Unit::Liter => "l",
+            Unit::Teaspoon => "cucchiaino",
+            Unit::Tablespoon => "cucchiaio",
+            Unit::Glass => "bicchiere",
+            Unit::Cup => "tazza",
+            Unit::Piece => "pz",
+            Unit::Clove => "spicchio",
+            Unit::Handful => "manciata",
+            Unit::Pinch => "pizzico",
+            Unit::Unknown(unit) => unit,
+        })
+    }
+}
+impl Serialize for Unit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+impl JsonSchema for Unit {
+    fn schema_name() -> String {
+        "Unit".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some(
+            "Unit of measure, like `g`, `ml` or `cucchiaio`. Unknown units are kept as written"
+                .to_owned(),
+        );
+        schema.into()
+    }
+}
+impl<'de> Deserialize<'de> for Unit {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <Cow<'de, str>>::deserialize(deserializer)?;
+        Ok(Self::from(&*s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_symbols_and_names() {
+        assert_eq!(Unit::from("g"), Unit::Gram);
+        assert_eq!(Unit::from("gr."), Unit::Gram);
+        assert_eq!(Unit::from("Kg"), Unit::Kilogram);
+        assert_eq!(Unit::from(" litri "), Unit::Liter);
+        assert_eq!(Unit::from("cucchiai"), Unit::Tablespoon);
+        assert_eq!(Unit::from("cucchiaino"), Unit::Teaspoon);
+        assert_eq!(Unit::from("spicchi"), Unit::Clove);
+    }
+
+    #[test]
+    fn keeps_unknown_units_as_written() {
+        let unit = Unit::from("Scatole");
+        assert_eq!(unit, Unit::Unknown("Scatole".to_owned()));
+        assert!(unit.is_unknown());
+        assert_eq!(unit.to_string(), "Scatole");
+        assert_eq!(unit.name(3.), "Scatole");
+        assert_eq!(unit.base(), None);
+    }
+
+    #[test]
+    fn converts_in_the_same_dimension() {
+        assert_eq!(Unit::Kilogram.convert(1.5, &Unit::Gram), Some(1500.));
+        assert_eq!(Unit::Hectogram.convert(2., &Unit::Gram), Some(200.));
+        assert_eq!(Unit::Tablespoon.convert(2., &Unit::Milliliter), Some(30.));
+        assert_eq!(Unit::Milliliter.convert(500., &Unit::Liter), Some(0.5));
+    }
+
+    #[test]
+    fn does_not_convert_across_dimensions() {
+        assert_eq!(Unit::Gram.convert(100., &Unit::Milliliter), None);
+        assert_eq!(Unit::Piece.convert(1., &Unit::Gram), None);
+        assert_eq!(Unit::Clove.convert(1., &Unit::Gram), None);
+        let unknown = Unit::from("scatola");
+        assert_eq!(unknown.convert(2., &Unit::Gram), None);
+        assert_eq!(unknown.convert(2., &Unit::from("scatola")), Some(2.));
+    }
+
+    #[test]
+    fn names_are_plural_over_one() {
+        assert_eq!(Unit::Tablespoon.name(1.), "cucchiaio");
+        assert_eq!(Unit::Tablespoon.name(2.), "cucchiai");
+        assert_eq!(Unit::Clove.name(0.5), "spicchio");
+        assert_eq!(Unit::Clove.name(1.5), "spicchi");
+        assert_eq!(Unit::Gram.name(200.), "g");
+    }
+}
@@ -0,0 +1,99 @@
+//! JSON Schemas of the hand written source files, for editor validation
+//!
+//! The schemas are stricter than the loading of the book: editors flag the unknown keys,
+//! that are otherwise only reported by the lints, and the `modified` key, that is read
+//! from the files themselves.
+
+use std::{
+    fs::{create_dir_all, File},
+    path::Path,
+};
+
+use anyhow::Context;
+use schemars::{
+    schema::{RootSchema, Schema},
+    schema_for,
+};
+
+use super::{
+    format::KEY_ALIASES, front_matter::FrontMatter, introduction::Metas, recipe::Recipe, tags::Tags,
+};
+
+/// Write the schemas of the recipe headers, of `front_matter.yml`, of `tags.yml`
+/// and of the introduction headers into the directory at `path`
+pub fn write_schemas(path: impl AsRef<Path>) -> anyhow::Result<()> {
+    log::info!("Writing schemas to {}", path.as_ref().display());
+    let path = path.as_ref();
+    create_dir_all(path).context("Cannot create schemas dir")?;
+
+    write_schema(
+        &path.join("recipe.schema.json"),
+        &strict(Recipe::header_schema()),
+    )
+    .context("While writing the recipe schema")?;
+    write_schema(
+        &path.join("front_matter.schema.json"),
+        &strict(schema_for!(FrontMatter)),
+    )
+    .context("While writing the front matter schema")?;
+    write_schema(&path.join("tags.schema.json"), &schema_for!(Tags))
+        .context("While writing the tags schema")?;
+    write_schema(
+        &path.join("introduction.schema.json"),
+        &strict(schema_for!(Metas)),
+    )
+    .context("While writing the introduction schema")?;
+    Ok(())
+}
+
+/// Reject the unknown keys of a header and accept the aliases of the known ones,
+/// leaving out `modified`
+fn strict(mut root: RootSchema) -> RootSchema {
+    let object = root.schema.object();
+    object.properties.remove("modified");
+    object.required.remove("modified");
+    for (alias, key) in KEY_ALIASES {
+        if let Some(schema) = object.properties.get(*key).cloned() {
+            object.properties.insert((*alias).to_owned(), schema);
+        }
+    }
+    object.additional_properties = Some(Box::new(Schema::Bool(false)));
+    root
+}
+
+fn write_schema(path: &Path, schema: &RootSchema) -> anyhow::Result<()> {
+    log::debug!("Writing {}", path.display());
+    serde_json::to_writer_pretty(File::create(path).context("Cannot create file")?, schema)
+        .context("While writing file")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::book::tests::recipe;
+
+    #[test]
+    fn quantities_in_the_schema_are_read_by_the_loader() {
+        let schema = serde_json::to_value(strict(Recipe::header_schema())).unwrap();
+        let required: Vec<_> = schema["definitions"]["Quantity"]["anyOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|variant| variant.get("required"))
+            .collect();
+        assert_eq!(required, [&json!(["n"]), &json!(["range"])]);
+
+        let recipe = recipe(
+            "time: 10m\ningredients:\n- name: Farina\n  quantity: {n: 200, unit: g}\n\
+            - name: Aglio\n  quantity: {range: [1, 2], unit: spicchio}\n- name: Sale",
+        );
+        let quantities: Vec<_> = recipe
+            .ingredients
+            .iter()
+            .map(|ingredient| ingredient.quantity().to_string())
+            .collect();
+        assert_eq!(quantities, ["200 g", "1-2 spicchi", "q.b."]);
+    }
+}
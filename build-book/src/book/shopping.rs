@@ -0,0 +1,216 @@
+//! Shopping list merging the ingredients of multiple recipes
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    num::NonZeroU32,
+    str::FromStr,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use super::{
+    language::{Label, Language},
+    recipe::{Ingredient, Quantity},
+    Book,
+};
+use crate::parsers::markdown::Markdown;
+
+/// A recipe to shop for, optionally scaled to a number of servings
+///
+/// Parsed from `slug` or `slug:servings`
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub recipe: String,
+    pub servings: Option<NonZeroU32>,
+}
+impl FromStr for Request {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            Some((recipe, servings)) => Self {
+                recipe: recipe.trim().to_owned(),
+                servings: Some(
+                    servings
+                        .trim()
+                        .parse()
+                        .context("The servings must be a positive integer")?,
+                ),
+            },
+            None => Self {
+                recipe: s.trim().to_owned(),
+                servings: None,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShoppingList {
+    pub items: Vec<Item>,
+    pub optional: Vec<Item>,
+    /// Language of the headings
+    #[serde(skip)]
+    pub language: Language,
+}
+impl ShoppingList {
+    pub fn new(book: &Book, requests: &[Request]) -> anyhow::Result<Self> {
+        let mut items = BTreeMap::new();
+        let mut optional = BTreeMap::new();
+
+        for Request { recipe, servings } in requests {
+            let mut recipe = book
+                .recipes
+                .get(recipe)
+                .with_context(|| format!("Unknown recipe `{recipe}`"))?
+                .clone();
+            if let Some(servings) = servings {
+                recipe.scale(*servings)
+            }
+            for ingredient in &book.expanded_ingredients(&recipe) {
+                let items = if ingredient.is_optional() {
+                    &mut optional
+                } else {
+                    &mut items
+                };
+                items
+                    .entry(ingredient.name().0.to_string().trim().to_lowercase())
+                    .or_insert_with(|| Item {
+                        name: ingredient.name().clone(),
+                        quantities: vec![],
+                    })
+                    .add(ingredient);
+            }
+        }
+
+        Ok(Self {
+            items: items.into_values().collect(),
+            optional: optional.into_values().collect(),
+            language: book.front_matter.language,
+        })
+    }
+}
+impl Display for ShoppingList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# {}", self.language.label(Label::ShoppingList))?;
+        writeln!(f)?;
+        for item in &self.items {
+            writeln!(f, "- {item}")?;
+        }
+        if !self.optional.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "## {}", self.language.label(Label::OptionalItems))?;
+            writeln!(f)?;
+            for item in &self.optional {
+                writeln!(f, "- {item}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub name: Markdown,
+    /// Quantities that could not be summed together, like ones in incompatible units
+    pub quantities: Vec<Quantity>,
+}
+impl Item {
+    fn add(&mut self, ingredient: &Ingredient) {
+        let quantity = ingredient.quantity();
+        if quantity.is_to_taste() {
+            // quantities to taste are listed only once
+            if !self.quantities.iter().any(Quantity::is_to_taste) {
+                self.quantities.push(Quantity::ToTaste)
+            }
+            return;
+        }
+        for existing in &mut self.quantities {
+            if let Some(sum) = existing.checked_add(quantity) {
+                *existing = sum;
+                return;
+            }
+        }
+        self.quantities.push(quantity.clone())
+    }
+}
+impl Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.0.to_string().trim())?;
+        for (i, quantity) in self.quantities.iter().enumerate() {
+            if i == 0 {
+                write!(f, " {quantity}")?;
+            } else {
+                write!(f, " + {quantity}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe};
+
+    fn list(requests: &[&str]) -> String {
+        let book = book([
+            (
+                "sugo",
+                recipe("time: 30m\ningredients: [Pomodoro 200-300 g, Aglio ? 1 spicchio, Sale]"),
+            ),
+            (
+                "pasta",
+                recipe("time: 10m\ningredients: [Pomodoro 0.1 kg, Aglio ? 2 spicchi, Sale, Olio 2 cucchiai]"),
+            ),
+        ]);
+        let requests: Vec<Request> = requests.iter().map(|r| r.parse().unwrap()).collect();
+        ShoppingList::new(&book, &requests).unwrap().to_string()
+    }
+
+    #[test]
+    fn sums_ranges_and_exact_quantities() {
+        let list = list(&["sugo", "pasta"]);
+        assert!(list.contains("- Pomodoro 300-400 g\n"));
+    }
+
+    #[test]
+    fn lists_to_taste_once() {
+        let list = list(&["sugo", "pasta"]);
+        assert!(list.contains("- Sale q.b.\n"));
+    }
+
+    #[test]
+    fn keeps_optional_ingredients_apart() {
+        let list = list(&["sugo", "pasta"]);
+        let (required, optional) = list.split_once("## Opzionali").unwrap();
+        assert!(!required.contains("Aglio"));
+        assert!(optional.contains("- Aglio 3 spicchi\n"));
+    }
+
+    #[test]
+    fn scales_to_the_servings() {
+        let list = list(&["pasta:3"]);
+        assert!(list.contains("- Olio 6 cucchiai\n"));
+    }
+
+    #[test]
+    fn rejects_unknown_recipes() {
+        let book = book([]);
+        let requests = ["ragu".parse().unwrap()];
+        assert!(ShoppingList::new(&book, &requests).is_err());
+    }
+
+    #[test]
+    fn headings_follow_the_language_of_the_book() {
+        let mut book = book([("sugo", recipe("time: 30m\ningredients: [Basilico ?]"))]);
+        book.front_matter.language = Language::English;
+        let list = ShoppingList::new(&book, &["sugo".parse().unwrap()])
+            .unwrap()
+            .to_string();
+        assert!(list.starts_with("# Shopping list\n"));
+        assert!(list.contains("## Optional\n"));
+    }
+}
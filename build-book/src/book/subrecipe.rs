@@ -0,0 +1,330 @@
+//! Recipes used as ingredients of other recipes
+//!
+//! An ingredient whose name links to a recipe of the book, like `[[ragu]] 2`, stands
+//! for that recipe. A quantity without unit is the number of servings needed, and a
+//! range of servings gives a range of each ingredient. Without a quantity the whole
+//! recipe is used, while quantities with a unit are rejected, as they cannot be turned
+//! into servings.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use chrono::Duration;
+use lazy_regex::Regex;
+
+use super::{
+    is_unknown_recipe, links,
+    recipe::{Ingredient, Quantity, Recipe},
+    recipe_file, Book,
+};
+use crate::{
+    diagnostic::{find_in_files, Errors},
+    parsers::markdown::Markdown,
+};
+
+/// Check that the recipes used as ingredients exist and are used by servings, and that
+/// no recipe ends up using itself
+///
+/// If the book was loaded from `root`, the errors point to the ingredients in the files.
+pub fn check(recipes: &BTreeMap<String, Recipe>, root: Option<&Path>) -> anyhow::Result<()> {
+    let mut errors = Errors::default();
+    for (name, recipe) in recipes {
+        for ingredient in &recipe.ingredients {
+            let Some(sub) = ingredient.recipe() else {
+                continue;
+            };
+            if is_unknown_recipe(recipes, root, &sub) {
+                errors.push(at_ingredient(
+                    root,
+                    name,
+                    &sub,
+                    anyhow!("Recipe `{name}` uses the unknown recipe `{sub}` as an ingredient"),
+                ))
+            }
+            if let Quantity::Exact { unit: Some(_), .. } | Quantity::Range { unit: Some(_), .. } =
+                ingredient.quantity()
+            {
+                errors.push(at_ingredient(
+                    root,
+                    name,
+                    &sub,
+                    anyhow!(
+                        "Recipe `{name}` uses `{}` of the recipe `{sub}`, give the number of servings instead",
+                        ingredient.quantity()
+                    ),
+                ))
+            }
+        }
+    }
+
+    let mut done = BTreeSet::new();
+    for name in recipes.keys() {
+        find_cycles(recipes, root, name, &mut vec![], &mut done, &mut errors)
+    }
+    errors.into_result(())
+}
+
+/// Point an error at the ingredient of the recipe `name` that uses the recipe `sub`
+fn at_ingredient(root: Option<&Path>, name: &str, sub: &str, err: anyhow::Error) -> anyhow::Error {
+    let line = Regex::new(&format!(
+        r"(?m)^[ \t]*(?:-[ \t]*)?(.*{}.*?)[ \t]*$",
+        links::pattern(sub).as_str()
+    ))
+    .expect("the link pattern is valid");
+    find_in_files(root.map(|root| recipe_file(root, name, None)), &line, err)
+}
+
+/// Depth first visit of the recipes used by `name`, reporting each cycle once
+fn find_cycles<'r>(
+    recipes: &'r BTreeMap<String, Recipe>,
+    root: Option<&Path>,
+    name: &'r str,
+    path: &mut Vec<&'r str>,
+    done: &mut BTreeSet<&'r str>,
+    errors: &mut Errors,
+) {
+    if let Some(start) = path.iter().position(|visiting| *visiting == name) {
+        let cycle: Vec<_> = path[start..].iter().chain([&name]).collect();
+        let err = anyhow!(
+            "Recipes used as ingredients form a cycle: {}",
+            cycle
+                .iter()
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        );
+        // the first recipe of the cycle uses the next one
+        errors.push(at_ingredient(root, cycle[0], cycle[1], err));
+        return;
+    }
+    let Some((name, recipe)) = recipes.get_key_value(name) else {
+        return;
+    };
+    if done.contains(name.as_str()) {
+        return;
+    }
+    path.push(name);
+    for ingredient in &recipe.ingredients {
+        if let Some(sub) = ingredient.recipe() {
+            if let Some((sub, _)) = recipes.get_key_value(&sub) {
+                find_cycles(recipes, root, sub, path, done, errors)
+            }
+        }
+    }
+    path.pop();
+    done.insert(name);
+}
+
+impl Book {
+    /// The recipes used as ingredients by `recipe`, with the fraction of each that is needed
+    fn subrecipes<'b>(&'b self, recipe: &'b Recipe) -> impl Iterator<Item = Sub<'b>> + 'b {
+        recipe.ingredients.iter().filter_map(|ingredient| {
+            let (name, recipe) = self.recipes.get_key_value(&ingredient.recipe()?)?;
+            let servings = f64::from(recipe.servings.get());
+            // quantities with a unit are rejected by `check`
+            let factor = match ingredient.quantity() {
+                Quantity::Exact { n, .. } => (n / servings, n / servings),
+                Quantity::Range {
+                    range: (min, max), ..
+                } => (min / servings, max / servings),
+                Quantity::ToTaste => (1., 1.),
+            };
+            Some(Sub {
+                name,
+                recipe,
+                factor,
+                optional: ingredient.is_optional(),
+            })
+        })
+    }
+
+    /// Time needed for `recipe`, including the recipes it uses
+    #[must_use]
+    pub fn total_time(&self, recipe: &Recipe) -> Duration {
+        self.total_time_inner(recipe, &mut vec![])
+    }
+    fn total_time_inner<'b>(&'b self, recipe: &'b Recipe, path: &mut Vec<&'b str>) -> Duration {
+        let mut time = recipe.time.total();
+        for sub in self.subrecipes(recipe) {
+            if path.contains(&sub.name) {
+                continue; // cycles are reported while loading
+            }
+            path.push(sub.name);
+            time = time + self.total_time_inner(sub.recipe, path);
+            path.pop();
+        }
+        time
+    }
+
+    /// Ingredients to buy for `recipe`, replacing the recipes it uses with their own
+    /// ingredients, scaled to the quantity needed
+    #[must_use]
+    pub fn expanded_ingredients(&self, recipe: &Recipe) -> Vec<Ingredient> {
+        self.expanded_ingredients_inner(recipe, &mut vec![])
+    }
+    fn expanded_ingredients_inner<'b>(
+        &'b self,
+        recipe: &'b Recipe,
+        path: &mut Vec<&'b str>,
+    ) -> Vec<Ingredient> {
+        let mut subs = self.subrecipes(recipe).peekable();
+        let mut ingredients = vec![];
+        for ingredient in &recipe.ingredients {
+            let Some(sub) = subs.next_if(|sub| ingredient.recipe().as_deref() == Some(sub.name))
+            else {
+                ingredients.push(ingredient.clone());
+                continue;
+            };
+            if path.contains(&sub.name) {
+                continue; // cycles are reported while loading
+            }
+            path.push(sub.name);
+            for mut sub_ingredient in self.expanded_ingredients_inner(sub.recipe, path) {
+                sub_ingredient.scale_range(sub.factor);
+                if sub.optional {
+                    sub_ingredient.set_optional(true)
+                }
+                ingredients.push(sub_ingredient)
+            }
+            path.pop();
+        }
+        ingredients
+    }
+
+    /// Tools needed for `recipe`, including the ones of the recipes it uses
+    #[must_use]
+    pub fn all_tools<'b>(&'b self, recipe: &'b Recipe) -> Vec<&'b Markdown> {
+        let mut tools = vec![];
+        let mut seen = BTreeSet::new();
+        self.all_tools_inner(recipe, &mut vec![], &mut tools, &mut seen);
+        tools
+    }
+    fn all_tools_inner<'b>(
+        &'b self,
+        recipe: &'b Recipe,
+        path: &mut Vec<&'b str>,
+        tools: &mut Vec<&'b Markdown>,
+        seen: &mut BTreeSet<String>,
+    ) {
+        for tool in &recipe.tools {
+            if seen.insert(tool.0.to_string().trim().to_lowercase()) {
+                tools.push(tool)
+            }
+        }
+        for sub in self.subrecipes(recipe) {
+            if path.contains(&sub.name) {
+                continue; // cycles are reported while loading
+            }
+            path.push(sub.name);
+            self.all_tools_inner(sub.recipe, path, tools, seen);
+            path.pop();
+        }
+    }
+}
+
+/// A recipe used as an ingredient
+struct Sub<'b> {
+    name: &'b str,
+    recipe: &'b Recipe,
+    /// Fraction of the recipe that is needed, between a minimum and a maximum
+    factor: (f64, f64),
+    optional: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe};
+
+    #[test]
+    fn unknown_recipes_are_reported() {
+        let book = book([("pasta", recipe("time: 10m\ningredients: [\"[[ragu]] 2\"]"))]);
+        let err = check(&book.recipes, None).unwrap_err();
+        assert!(format!("{err:#}").contains("unknown recipe `ragu`"));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let book = book([
+            ("a", recipe("time: 10m\ningredients: [\"[[b]] 1\"]")),
+            ("b", recipe("time: 10m\ningredients: [\"[[c]] 1\"]")),
+            ("c", recipe("time: 10m\ningredients: [\"[[a]] 1\"]")),
+        ]);
+        let err = check(&book.recipes, None).unwrap_err();
+        assert!(format!("{err:#}").contains("cycle: `a` -> `b` -> `c` -> `a`"));
+    }
+
+    #[test]
+    fn quantities_with_a_unit_are_rejected() {
+        let book = book([
+            ("ragu", recipe("time: 1h\ningredients: [Carne 400 g]")),
+            (
+                "pasta",
+                recipe("time: 10m\ningredients: [\"[[ragu]] 200 g\"]"),
+            ),
+        ]);
+        let err = check(&book.recipes, None).unwrap_err();
+        assert!(format!("{err:#}").contains("uses `200 g` of the recipe `ragu`"));
+    }
+
+    #[test]
+    fn total_time_includes_the_nested_recipes() {
+        let book = book([
+            ("brodo", recipe("time: 2h\ningredients: [Acqua]")),
+            ("ragu", recipe("time: 1h\ningredients: [\"[[brodo]] 1\"]")),
+            ("pasta", recipe("time: 10m\ningredients: [\"[[ragu]] 1\"]")),
+        ]);
+        check(&book.recipes, None).unwrap();
+        assert_eq!(
+            book.total_time(&book.recipes["pasta"]),
+            Duration::minutes(190)
+        );
+    }
+
+    #[test]
+    fn ingredients_are_scaled_by_the_servings() {
+        let book = book([
+            (
+                "ragu",
+                recipe("time: 1h\nservings: 4\ningredients: [Carne 400 g, Sale]"),
+            ),
+            (
+                "pasta",
+                recipe("time: 10m\ningredients: [\"[[ragu]] 1-2\"]"),
+            ),
+        ]);
+        let ingredients: Vec<_> = book
+            .expanded_ingredients(&book.recipes["pasta"])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(ingredients, ["Carne 100-200 g", "Sale"]);
+    }
+
+    #[test]
+    fn tools_include_the_nested_recipes_once() {
+        let book = book([
+            (
+                "brodo",
+                recipe("time: 2h\ningredients: [Acqua]\ntools: [Pentola, Colino]"),
+            ),
+            (
+                "ragu",
+                recipe("time: 1h\ningredients: ['[[brodo]] 1']\ntools: [Tegame, pentola]"),
+            ),
+            (
+                "pasta",
+                recipe("time: 10m\ningredients: ['[[ragu]] 1']\ntools: [Pentola]"),
+            ),
+        ]);
+        let tools: Vec<_> = book
+            .all_tools(&book.recipes["pasta"])
+            .iter()
+            .map(|tool| tool.0.to_string())
+            .collect();
+        assert_eq!(tools, ["Pentola", "Tegame", "Colino"]);
+    }
+}
@@ -0,0 +1,262 @@
+//! Vocabulary of the tags the recipes can use
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{read_to_string, File},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use lazy_regex::{regex::escape, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{recipe::Recipe, recipe_file};
+use crate::{
+    diagnostic::{find_in_files, Errors, InFile},
+    parsers::markdown::Markdown,
+};
+
+/// The tags declared in `tags.yml`, by the name used in the recipes
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Tags(pub BTreeMap<String, Tag>);
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Tag {
+    /// Name shown to the readers
+    pub name: Markdown,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Markdown>,
+    /// Broader tag this one belongs to, like `primi` for `pasta`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Name used in the urls. Defaults to the tag itself, without accents and spaces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+}
+
+impl Tags {
+    /// Load the vocabulary, if the book has one
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            log::debug!("No tag vocabulary at {}", path.display());
+            return Ok(None);
+        }
+        log::info!("Loading tags from {}", path.display());
+        let source = read_to_string(path)
+            .context("While reading file")
+            .in_file(path, None)?;
+        let tags = serde_yaml::from_str(&source)
+            .context("Cannot parse file")
+            .in_file(path, Some(&source))?;
+        Ok(Some(tags))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        log::info!("Saving tags to {}", path.as_ref().display());
+        serde_yaml::to_writer(File::create(path).context("Cannot create file")?, self)
+            .context("While writing file")
+    }
+
+    /// Check that the vocabulary is consistent, and that the recipes use only the
+    /// tags it declares
+    ///
+    /// If the book was loaded from `root`, the errors point to its files.
+    pub fn check(
+        &self,
+        recipes: &BTreeMap<String, Recipe>,
+        root: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let mut errors = Errors::default();
+
+        let in_tags = |pattern: String, err: anyhow::Error| {
+            let pattern = Regex::new(&pattern).expect("the names are escaped");
+            find_in_files(root.map(|root| root.join("tags.yml")), &pattern, err)
+        };
+        // the line declaring the tag
+        let declaration = |name: &str| format!(r#"(?m)^["']?({})["']?:"#, escape(name));
+        let mut slugs = BTreeMap::new();
+        for (name, tag) in &self.0 {
+            if let Some(parent) = &tag.parent {
+                if !self.0.contains_key(parent) {
+                    errors.push(in_tags(
+                        format!(
+                            r#"(?m)^["']?{}["']?:[ \t]*\n(?:[ \t]+.*\n)*?[ \t]+parent:[ \t]*["']?({})"#,
+                            escape(name),
+                            escape(parent)
+                        ),
+                        anyhow!("Tag `{name}` has the unknown parent `{parent}`"),
+                    ))
+                }
+            }
+            if self.ancestors(name).any(|ancestor| ancestor == name) {
+                errors.push(in_tags(
+                    declaration(name),
+                    anyhow!("Tag `{name}` is its own ancestor"),
+                ))
+            }
+            let slug = self.slug(name);
+            if slug.is_empty() || !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                errors.push(in_tags(
+                    declaration(name),
+                    anyhow!(
+                        "Tag `{name}` has the slug `{slug}`, that can contain only ascii letters, digits and `-`"
+                    ),
+                ))
+            }
+            if let Some(other) = slugs.insert(slug.clone(), name) {
+                errors.push(in_tags(
+                    declaration(name),
+                    anyhow!("Tags `{other}` and `{name}` have the same slug `{slug}`"),
+                ))
+            }
+        }
+
+        for (recipe_name, recipe) in recipes {
+            for tag in &recipe.tags {
+                if self.0.contains_key(tag) {
+                    continue;
+                }
+                let similar = self
+                    .0
+                    .keys()
+                    .find(|known| known.to_lowercase() == tag.trim().to_lowercase());
+                let err = match similar {
+                    Some(similar) => anyhow!(
+                        "Recipe `{recipe_name}` has the unknown tag `{tag}`, did you mean `{similar}`?"
+                    ),
+                    None => anyhow!(
+                        "Recipe `{recipe_name}` has the unknown tag `{tag}`, declare it in `tags.yml`"
+                    ),
+                };
+                // the entry in the `tags` list of the header, either a block sequence or
+                // a flow one like `tags: [Primo, Veloce]`
+                let entry = Regex::new(&format!(
+                    r#"(?m)(?:^[ \t]*-|^tags:[ \t]*\[(?:[^\]]*,)?)\s*["']?({})["']?[ \t]*(?:$|[,\]])"#,
+                    escape(tag)
+                ))
+                .expect("the tag is escaped");
+                errors.push(find_in_files(
+                    root.map(|root| recipe_file(root, recipe_name, None)),
+                    &entry,
+                    err,
+                ))
+            }
+        }
+
+        errors.into_result(())
+    }
+
+    /// The slug of a tag, used to build its url
+    #[must_use]
+    pub fn slug(&self, name: &str) -> String {
+        match self.0.get(name).and_then(|tag| tag.slug.as_ref()) {
+            Some(slug) => slug.clone(),
+            None => slugify(name),
+        }
+    }
+
+    /// The parents of a tag, from the nearest one
+    ///
+    /// Stops before repeating a tag if the hierarchy has a cycle
+    pub fn ancestors<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'s str> + 's {
+        let mut seen = BTreeSet::new();
+        let mut current = name;
+        std::iter::from_fn(move || {
+            let parent = self.0.get(current)?.parent.as_deref()?;
+            if !seen.insert(parent) {
+                return None;
+            }
+            current = parent;
+            Some(parent)
+        })
+    }
+
+    /// The tags that have `name` as their parent
+    pub fn children<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'s str> + 's {
+        self.0
+            .iter()
+            .filter(move |(_, tag)| tag.parent.as_deref() == Some(name))
+            .map(|(child, _)| child.as_str())
+    }
+
+    /// The tags at the top of the hierarchy
+    pub fn roots(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .filter(|(_, tag)| tag.parent.is_none())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Check if a recipe is tagged with `name`, or with any of its descendants
+    #[must_use]
+    pub fn is_tagged(&self, recipe: &Recipe, name: &str) -> bool {
+        recipe
+            .tags
+            .iter()
+            .any(|tag| tag == name || self.ancestors(tag).any(|ancestor| ancestor == name))
+    }
+}
+
+/// Turn a tag into an url friendly name, dropping accents and replacing spaces with `-`
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        let c = match c {
+            'à' | 'á' => 'a',
+            'è' | 'é' => 'e',
+            'ì' | 'í' => 'i',
+            'ò' | 'ó' => 'o',
+            'ù' | 'ú' => 'u',
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '-',
+        };
+        if c != '-' || !(slug.is_empty() || slug.ends_with('-')) {
+            slug.push(c)
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe, TempBook};
+
+    fn unknown_tag_error(tags: &str) -> String {
+        let book = book([(
+            "sugo",
+            recipe(&format!("time: 10m\ningredients: [Sale]\ntags: {tags}")),
+        )]);
+        let sources = TempBook::new(&book, "unknown-tag");
+        std::fs::write(
+            sources.0.join("recipes/sugo.md"),
+            format!("---\nname: Sugo\ntime: 10m\ntools: []\ntags: {tags}\n---\n"),
+        )
+        .unwrap();
+        let err = Tags::default()
+            .check(&book.recipes, Some(&sources.0))
+            .expect_err("the tag is unknown");
+        format!("{err:#}")
+    }
+
+    #[test]
+    fn unknown_tags_point_to_their_line() {
+        let message = unknown_tag_error("\n  - Ignoto");
+        assert!(message.contains("sugo.md:6"), "{message}");
+        assert!(message.contains("- Ignoto"), "{message}");
+    }
+
+    #[test]
+    fn unknown_tags_in_flow_sequences_point_to_their_line() {
+        let message = unknown_tag_error("[Ignoto]");
+        assert!(message.contains("sugo.md:5"), "{message}");
+        assert!(message.contains("tags: [Ignoto]"), "{message}");
+
+        let message = unknown_tag_error("['Ignoto', Ignoto2]");
+        assert!(message.contains("sugo.md:5:18"), "{message}");
+    }
+}
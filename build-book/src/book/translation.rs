@@ -0,0 +1,308 @@
+//! Translations of the book in other languages
+//!
+//! A translated file sits beside its original, with the code of the language before
+//! the extension, like `recipes/reginette.en.md` or `introduction/zen.en.md`. What is
+//! not translated falls back to the main language of the book.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{introduction::IntroductionTranslation, language::Language, recipe::Recipe, Book};
+use crate::diagnostic::Errors;
+
+/// The parts of the book translated in a language
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Translation {
+    #[serde(default, skip_serializing_if = "IntroductionTranslation::is_empty")]
+    pub introduction: IntroductionTranslation,
+    /// Translated recipes, by the name of the original
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub recipes: BTreeMap<String, Recipe>,
+}
+
+/// Split a file name like `reginette.en` in the name of the original and the language
+#[must_use]
+pub fn split_name(name: &str) -> (&str, Option<Language>) {
+    match name.rsplit_once('.') {
+        Some((original, code)) => match Language::from_code(code) {
+            Some(language) => (original, Some(language)),
+            None => (name, None),
+        },
+        None => (name, None),
+    }
+}
+
+/// Move the translated recipes out of the recipes loaded from the source files
+pub fn take_recipes(
+    recipes: &mut BTreeMap<String, Recipe>,
+    main: Language,
+) -> anyhow::Result<BTreeMap<Language, BTreeMap<String, Recipe>>> {
+    let translated: Vec<String> = recipes
+        .keys()
+        .filter(|name| split_name(name).1.is_some())
+        .cloned()
+        .collect();
+
+    let mut translations: BTreeMap<_, BTreeMap<_, _>> = BTreeMap::new();
+    for name in translated {
+        let recipe = recipes.remove(&name).unwrap();
+        let (original, Some(language)) = split_name(&name) else {
+            unreachable!("only the translations were selected")
+        };
+        translations
+            .entry(language)
+            .or_default()
+            .insert(original.to_owned(), recipe);
+    }
+    check_recipes(
+        recipes,
+        main,
+        translations
+            .iter()
+            .map(|(language, translated)| (*language, translated)),
+    )?;
+    Ok(translations)
+}
+
+/// Put together the translations of the introduction and of the recipes
+#[must_use]
+pub fn gather(
+    introductions: BTreeMap<Language, IntroductionTranslation>,
+    recipes: BTreeMap<Language, BTreeMap<String, Recipe>>,
+) -> BTreeMap<Language, Translation> {
+    let mut translations: BTreeMap<Language, Translation> = BTreeMap::new();
+    for (language, introduction) in introductions {
+        translations.entry(language).or_default().introduction = introduction;
+    }
+    for (language, recipes) in recipes {
+        translations.entry(language).or_default().recipes = recipes;
+    }
+    translations
+}
+
+/// Check that the translations are in another language, and of existing recipes
+pub fn check(book: &Book) -> anyhow::Result<()> {
+    let main = book.front_matter.language;
+    let mut errors = Errors::default();
+    if book
+        .translations
+        .get(&main)
+        .is_some_and(|translation| !translation.introduction.is_empty())
+    {
+        errors.push(anyhow!(
+            "The introduction is translated in `{main}`, that is already the main language of the book"
+        ))
+    }
+    errors.collect(check_recipes(
+        &book.recipes,
+        main,
+        book.translations
+            .iter()
+            .map(|(language, translation)| (*language, &translation.recipes)),
+    ));
+    errors.into_result(())
+}
+
+fn check_recipes<'r>(
+    recipes: &BTreeMap<String, Recipe>,
+    main: Language,
+    translations: impl IntoIterator<Item = (Language, &'r BTreeMap<String, Recipe>)>,
+) -> anyhow::Result<()> {
+    let mut errors = Errors::default();
+    for (language, translated) in translations {
+        for name in translated.keys() {
+            if language == main {
+                errors.push(anyhow!(
+                    "`{name}.{language}.md` is translated in `{language}`, that is already the main language of the book"
+                ))
+            } else if !recipes.contains_key(name) {
+                errors.push(anyhow!(
+                    "`{name}.{language}.md` is the translation of the unknown recipe `{name}`"
+                ))
+            }
+        }
+    }
+    errors.into_result(())
+}
+
+/// A translation older than its original, that probably misses some changes
+#[derive(Debug, Clone)]
+pub struct Stale {
+    pub language: Language,
+    /// The original file, relative to the book
+    pub original: String,
+}
+impl Display for Stale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { language, original } = self;
+        let translated = original
+            .strip_suffix(".md")
+            .map_or_else(|| original.clone(), |name| format!("{name}.{language}.md"));
+        write!(
+            f,
+            "`{translated}` is older than its original `{original}`, it could be missing some changes"
+        )
+    }
+}
+
+/// Find the translations modified before their original
+#[must_use]
+pub fn stale(book: &Book) -> Vec<Stale> {
+    fn older(translated: Option<DateTime<Utc>>, original: Option<DateTime<Utc>>) -> bool {
+        matches!((translated, original), (Some(translated), Some(original)) if translated < original)
+    }
+
+    let mut stale = vec![];
+    for (language, translation) in &book.translations {
+        for (page, translated, original) in translation.introduction.pages(&book.introduction) {
+            if older(translated.modified, original.modified) {
+                stale.push(Stale {
+                    language: *language,
+                    original: format!("introduction/{page}.md"),
+                })
+            }
+        }
+        for (name, translated) in &translation.recipes {
+            let original = book.recipes.get(name).and_then(Recipe::modified);
+            if older(translated.modified(), original) {
+                stale.push(Stale {
+                    language: *language,
+                    original: format!("recipes/{name}.md"),
+                })
+            }
+        }
+    }
+    stale
+}
+
+impl Book {
+    /// The languages the book is available in, starting from the main one
+    #[must_use]
+    pub fn languages(&self) -> Vec<Language> {
+        let mut languages = vec![self.front_matter.language];
+        languages.extend(
+            self.translations
+                .keys()
+                .filter(|language| **language != self.front_matter.language),
+        );
+        languages
+    }
+
+    /// The book in a single language, using the main one for what is not translated
+    #[must_use]
+    pub fn translated(&self, language: Language) -> Book {
+        let mut book = Book {
+            translations: BTreeMap::new(),
+            ..self.clone()
+        };
+        if language == self.front_matter.language {
+            return book;
+        }
+        book.front_matter.language = language;
+        if let Some(translation) = self.translations.get(&language) {
+            translation.introduction.apply(&mut book.introduction);
+            for (name, recipe) in &translation.recipes {
+                book.recipes.insert(name.clone(), recipe.clone());
+            }
+        }
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::tests::{book, recipe};
+
+    #[test]
+    fn names_of_the_translations() {
+        assert_eq!(split_name("sugo.en"), ("sugo", Some(Language::English)));
+        assert_eq!(split_name("sugo"), ("sugo", None));
+        assert_eq!(split_name("sugo.xx"), ("sugo.xx", None));
+    }
+
+    #[test]
+    fn untranslated_parts_fall_back_to_the_main_language() {
+        let mut book = book([
+            ("pesto", recipe("time: 10m\ningredients: [Basilico]")),
+            ("sugo", recipe("time: 10m\ningredients: [Pomodoro]")),
+        ]);
+        let mut sauce = recipe("time: 10m\ningredients: [Tomato]");
+        sauce.name = serde_yaml::from_str("Sauce").unwrap();
+        let introduction =
+            serde_yaml::from_str("prologue: { metas: { title: Prologue }, content: Prologue }")
+                .unwrap();
+        book.translations = gather(
+            [(Language::English, introduction)].into(),
+            [(Language::English, [("sugo".to_owned(), sauce)].into())].into(),
+        );
+
+        let english = book.translated(Language::English);
+        assert_eq!(english.front_matter.language, Language::English);
+        assert!(english.translations.is_empty());
+        assert_eq!(english.recipes["sugo"].name.0.to_string(), "Sauce");
+        assert_eq!(english.recipes["pesto"].name.0.to_string(), "Prova");
+        assert_eq!(
+            english.introduction.prologue.content.0.to_string(),
+            "Prologue"
+        );
+        assert_eq!(english.introduction.thanks.content.0.to_string(), "Grazie");
+
+        let italian = book.translated(Language::Italian);
+        assert_eq!(italian.recipes["sugo"].name.0.to_string(), "Prova");
+        assert_eq!(book.languages(), [Language::Italian, Language::English]);
+    }
+
+    #[test]
+    fn translations_need_an_original_in_another_language() {
+        let mut recipes: BTreeMap<_, _> = [
+            ("sugo", "time: 10m\ningredients: [Pomodoro]"),
+            ("sugo.en", "time: 10m\ningredients: [Tomato]"),
+            ("sugo.it", "time: 10m\ningredients: [Pomodoro]"),
+            ("pesto.en", "time: 10m\ningredients: [Basil]"),
+        ]
+        .into_iter()
+        .map(|(name, header)| (name.to_owned(), recipe(header)))
+        .collect();
+
+        let err = take_recipes(&mut recipes, Language::Italian).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("`sugo.it.md` is translated in `it`"),
+            "{message}"
+        );
+        assert!(
+            message.contains("`pesto.en.md` is the translation of the unknown recipe `pesto`"),
+            "{message}"
+        );
+        assert!(!message.contains("sugo.en"), "{message}");
+        assert_eq!(recipes.keys().collect::<Vec<_>>(), ["sugo"]);
+    }
+
+    #[test]
+    fn older_translations_are_stale() {
+        let now = Utc::now();
+        let mut sugo = recipe("time: 10m\ningredients: [Pomodoro]");
+        sugo.modified = Some(now);
+        let mut sauce = sugo.clone();
+        sauce.modified = Some(now - chrono::Duration::days(1));
+        let mut book = book([("sugo", sugo)]);
+        book.translations = gather(
+            BTreeMap::new(),
+            [(Language::English, [("sugo".to_owned(), sauce)].into())].into(),
+        );
+
+        let stale: Vec<_> = stale(&book).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            stale,
+            ["`recipes/sugo.en.md` is older than its original `recipes/sugo.md`, it could be missing some changes"]
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Book kept in sync with its source files
+//!
+//! Each source file is loaded on its own, so an invalid file does not prevent the
+//! others from being reloaded when they change. The files are polled, as the
+//! modification times are all that is needed. The images used by the book are watched
+//! too, so the outputs copying them are written again when they change.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::read_dir,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+
+use super::{
+    assets,
+    front_matter::FrontMatter,
+    introduction::{Introduction, IntroductionTranslation},
+    language::Language,
+    recipe::Recipe,
+    tags::Tags,
+    translation,
+};
+use crate::{diagnostic::Errors, Book};
+
+/// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct WatchedBook {
+    path: PathBuf,
+    front_matter: anyhow::Result<FrontMatter>,
+    introduction: anyhow::Result<Introduction>,
+    translated_introduction: anyhow::Result<BTreeMap<Language, IntroductionTranslation>>,
+    tags: anyhow::Result<Option<Tags>>,
+    recipes: BTreeMap<String, anyhow::Result<Recipe>>,
+    /// Images used by the book, in the book directory
+    images: BTreeSet<PathBuf>,
+    /// Modification times of the source files at the last check
+    modified: BTreeMap<PathBuf, SystemTime>,
+}
+impl WatchedBook {
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        log::info!("Loading book from {}", path.as_ref().display());
+        let path = path.as_ref().to_owned();
+        let mut book = Self {
+            front_matter: FrontMatter::load(path.join("front_matter.yml")),
+            introduction: Introduction::load(path.join("introduction")),
+            translated_introduction: Ok(BTreeMap::new()),
+            tags: Tags::load(path.join("tags.yml")),
+            recipes: BTreeMap::new(),
+            images: BTreeSet::new(),
+            modified: sources(&path, &BTreeSet::new()),
+            path,
+        };
+        book.load_translated_introduction();
+        book.load_recipes();
+        book.update_images();
+        book
+    }
+
+    /// Find again the images used by the book, that are watched with the sources
+    ///
+    /// While the book is invalid the images of the last valid one are kept.
+    fn update_images(&mut self) {
+        let Ok(images) = self.book().and_then(|book| assets::images(&book)) else {
+            return;
+        };
+        let images: BTreeSet<_> = images
+            .into_keys()
+            .map(|image| self.path.join(image))
+            .collect();
+        // the images that are used from now on start from their current time
+        self.modified
+            .retain(|file, _| !self.images.contains(file) || images.contains(file));
+        for (file, modified) in sources(&self.path, &images) {
+            self.modified.entry(file).or_insert(modified);
+        }
+        self.images = images;
+    }
+
+    /// The language of the book, deciding the headings of the recipes
+    fn language(&self) -> Language {
+        self.front_matter.as_ref().map_or_else(
+            |_| Language::default(),
+            |front_matter| front_matter.language,
+        )
+    }
+
+    /// Load all the recipes again
+    fn load_recipes(&mut self) {
+        self.recipes.clear();
+        for file in self.modified.keys().cloned().collect::<Vec<_>>() {
+            self.load_recipe(&file)
+        }
+    }
+
+    /// Load a recipe or a translation, with the headings of its language
+    fn load_recipe(&mut self, file: &Path) {
+        if let Some(name) = recipe_name(&self.path, file) {
+            let language = translation::split_name(&name)
+                .1
+                .unwrap_or_else(|| self.language());
+            self.recipes.insert(name, Recipe::load(file, language));
+        }
+    }
+
+    fn load_translated_introduction(&mut self) {
+        self.translated_introduction =
+            IntroductionTranslation::load_all(self.path.join("introduction"), self.language())
+    }
+
+    /// The book, if all the files are valid
+    pub fn book(&self) -> anyhow::Result<Book> {
+        let mut errors = Errors::default();
+        let failed = [
+            self.front_matter.as_ref().err(),
+            self.introduction.as_ref().err(),
+            self.translated_introduction.as_ref().err(),
+            self.tags.as_ref().err(),
+        ]
+        .into_iter()
+        .chain(self.recipes.values().map(|recipe| recipe.as_ref().err()))
+        .flatten();
+        // the errors are kept to be shown again until the file is fixed
+        for err in failed {
+            match err.downcast_ref::<Errors>() {
+                Some(Errors(errs)) => {
+                    for err in errs {
+                        errors.push(anyhow!("{err:#}"))
+                    }
+                }
+                None => errors.push(anyhow!("{err:#}")),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+
+        let (Ok(front_matter), Ok(introduction), Ok(translated_introduction), Ok(tags)) = (
+            &self.front_matter,
+            &self.introduction,
+            &self.translated_introduction,
+            &self.tags,
+        ) else {
+            unreachable!("errors were checked above")
+        };
+        let mut recipes = self
+            .recipes
+            .iter()
+            .filter_map(|(name, recipe)| Some((name.clone(), recipe.as_ref().ok()?.clone())))
+            .collect();
+        let translated_recipes = translation::take_recipes(&mut recipes, front_matter.language)?;
+        let book = Book {
+            front_matter: front_matter.clone(),
+            introduction: introduction.clone(),
+            recipes,
+            tags: tags.clone(),
+            translations: translation::gather(translated_introduction.clone(), translated_recipes),
+            root: Some(self.path.clone()),
+        };
+        book.check()?;
+        Ok(book)
+    }
+
+    /// Wait until some source files change, and reload them
+    pub fn wait_changes(&mut self) {
+        loop {
+            sleep(POLL_INTERVAL);
+            let modified = sources(&self.path, &self.images);
+            if modified == self.modified {
+                continue;
+            }
+            // let the editor finish writing
+            sleep(POLL_INTERVAL);
+            let modified = sources(&self.path, &self.images);
+
+            let mut changed: Vec<PathBuf> = modified
+                .iter()
+                .filter(|(file, time)| self.modified.get(*file) != Some(time))
+                .map(|(file, _)| file.clone())
+                .collect();
+            changed.extend(
+                self.modified
+                    .keys()
+                    .filter(|file| !modified.contains_key(*file))
+                    .cloned(),
+            );
+            self.modified = modified;
+            for file in changed {
+                self.reload(&file)
+            }
+            self.update_images();
+            return;
+        }
+    }
+
+    /// Reload a single changed file
+    fn reload(&mut self, file: &Path) {
+        log::info!("{} changed", file.display());
+        if file == self.path.join("front_matter.yml") {
+            let language = self.language();
+            self.front_matter = FrontMatter::load(file);
+            if self.language() != language {
+                log::info!("The language of the book changed, reloading all the recipes");
+                self.load_recipes();
+                self.load_translated_introduction()
+            }
+        } else if file == self.path.join("tags.yml") {
+            self.tags = Tags::load(file)
+        } else if file.parent() == Some(&self.path.join("introduction")) {
+            self.introduction = Introduction::load(self.path.join("introduction"));
+            self.load_translated_introduction()
+        } else if let Some(name) = recipe_name(&self.path, file) {
+            if file.exists() {
+                self.load_recipe(file)
+            } else {
+                log::info!("Recipe `{name}` was removed");
+                self.recipes.remove(&name);
+            }
+        }
+    }
+}
+
+/// The source files of the book at `path` and the `images` it uses, with their
+/// modification time
+fn sources(path: &Path, images: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, SystemTime> {
+    let mut files = vec![path.join("front_matter.yml"), path.join("tags.yml")];
+    files.extend(images.iter().cloned());
+    for dir in ["introduction", "recipes"] {
+        let Ok(entries) = read_dir(path.join(dir)) else {
+            continue;
+        };
+        files.extend(
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|file| file.extension().is_some_and(|ext| ext == "md")),
+        );
+    }
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let modified = file.metadata().and_then(|m| m.modified()).ok()?;
+            Some((file, modified))
+        })
+        .collect()
+}
+
+/// The name of the recipe in `file`, if it is a recipe file
+fn recipe_name(path: &Path, file: &Path) -> Option<String> {
+    if file.parent()? != path.join("recipes") || file.extension()? != "md" {
+        return None;
+    }
+    Some(file.file_stem()?.to_str()?.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, write, File};
+
+    use super::*;
+    use crate::book::tests::{book, recipe, TempBook};
+
+    /// Write `content` to `file`, making sure its modification time changes
+    fn touch(file: &Path, content: &str) {
+        write(file, content).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(file)
+            .and_then(|file| file.set_modified(later))
+            .unwrap();
+    }
+
+    #[test]
+    fn recipes_follow_their_files() {
+        let sources = TempBook::new(
+            &book([("sugo", recipe("time: 10m\ningredients: [Sale]"))]),
+            "watch",
+        );
+        let sugo = sources.0.join("recipes/sugo.md");
+        let pesto = sources.0.join("recipes/pesto.md");
+        let mut watched = WatchedBook::load(&sources.0);
+        assert!(watched.book().is_ok());
+
+        // an invalid file is reported until it is fixed
+        let valid = std::fs::read_to_string(&sugo).unwrap();
+        touch(&sugo, &valid.replace("time: 10m", "time: 10x"));
+        watched.wait_changes();
+        let err = watched.book().unwrap_err();
+        assert!(format!("{err:#}").contains("sugo.md"), "{err:#}");
+
+        touch(&sugo, &valid.replace("time: 10m", "time: 20m"));
+        touch(&pesto, &valid.replace("Prova", "Pesto"));
+        watched.wait_changes();
+        let book = watched.book().unwrap();
+        assert_eq!(book.recipes.keys().collect::<Vec<_>>(), ["pesto", "sugo"]);
+        assert_eq!(
+            book.recipes["sugo"].time.total(),
+            chrono::Duration::minutes(20)
+        );
+
+        remove_file(&pesto).unwrap();
+        watched.wait_changes();
+        let book = watched.book().unwrap();
+        assert_eq!(book.recipes.keys().collect::<Vec<_>>(), ["sugo"]);
+    }
+
+    #[test]
+    fn only_markdown_files_in_recipes_are_recipes() {
+        let root = Path::new("book");
+        assert_eq!(
+            recipe_name(root, &root.join("recipes/sugo.en.md")),
+            Some("sugo.en".to_owned())
+        );
+        assert_eq!(recipe_name(root, &root.join("recipes/sugo.jpg")), None);
+        assert_eq!(recipe_name(root, &root.join("introduction/zen.md")), None);
+    }
+}
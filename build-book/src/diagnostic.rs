@@ -0,0 +1,331 @@
+//! Errors pointing to a position in the book source files
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use lazy_regex::{regex_replace_all, Regex};
+use markdown::{
+    mdast::Node,
+    unist::{Point, Position},
+};
+
+/// An error located at a span of the parsed source
+#[derive(Debug)]
+pub struct Located {
+    pub position: Position,
+    pub message: String,
+}
+impl Located {
+    pub fn new(position: Position, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Locate an error at a markdown node, or at the start of the file if the node has no position
+    pub fn at(node: &Node, message: impl Into<String>) -> Self {
+        Self::new(
+            node.position()
+                .cloned()
+                .unwrap_or_else(|| Position::new(1, 1, 0, 1, 1, 0)),
+            message,
+        )
+    }
+
+    /// Locate an error from a yaml block that starts at `start` in the source
+    pub fn yaml(err: &serde_yaml::Error, start: &Point) -> Self {
+        let message = strip_yaml_location(&err.to_string());
+        let point = match err.location() {
+            Some(location) => Point::new(
+                start.line + location.line(),
+                location.column(),
+                start.offset + location.index(),
+            ),
+            None => start.clone(),
+        };
+        Self::new(
+            Position {
+                start: point.clone(),
+                end: point,
+            },
+            message,
+        )
+    }
+}
+impl Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+impl Error for Located {}
+
+/// An error in a source file, shown with a snippet of the offending line
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub position: Option<Position>,
+    pub message: String,
+    snippet: Option<String>,
+}
+impl Diagnostic {
+    /// Build a diagnostic from an error that happened while reading `path`
+    ///
+    /// The position is taken from the first [`Located`] or yaml error in the chain,
+    /// while `source` is used to show the offending line
+    pub fn new(path: impl Into<PathBuf>, source: Option<&str>, err: anyhow::Error) -> Self {
+        let position = err.chain().find_map(|err| {
+            if let Some(located) = err.downcast_ref::<Located>() {
+                Some(located.position.clone())
+            } else {
+                let location = err.downcast_ref::<serde_yaml::Error>()?.location()?;
+                let point = Point::new(location.line(), location.column(), location.index());
+                Some(Position {
+                    start: point.clone(),
+                    end: point,
+                })
+            }
+        });
+        let snippet = position.as_ref().and_then(|position| {
+            source?
+                .lines()
+                .nth(position.start.line.checked_sub(1)?)
+                .map(ToOwned::to_owned)
+        });
+        let message = format!("{err:#}");
+        Self {
+            path: path.into(),
+            message: if position.is_some() {
+                strip_yaml_location(&message)
+            } else {
+                message
+            },
+            position,
+            snippet,
+        }
+    }
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.message)?;
+        let Some(position) = &self.position else {
+            return write!(f, " --> {}", self.path.display());
+        };
+        let Position { start, end, .. } = position;
+        write!(
+            f,
+            " --> {}:{}:{}",
+            self.path.display(),
+            start.line,
+            start.column
+        )?;
+        if let Some(snippet) = &self.snippet {
+            let line = start.line.to_string();
+            let pad = " ".repeat(line.len());
+            let indent = start.column.saturating_sub(1);
+            let len = if end.line == start.line && end.column > start.column {
+                end.column - start.column
+            } else {
+                snippet.chars().count().saturating_sub(indent).max(1)
+            };
+            writeln!(f)?;
+            writeln!(f, "{pad} |")?;
+            writeln!(f, "{line} | {snippet}")?;
+            write!(f, "{pad} | {}{}", " ".repeat(indent), "^".repeat(len))?;
+        }
+        Ok(())
+    }
+}
+impl Error for Diagnostic {}
+
+/// Point an error found after loading the book at the first match of `pattern` in
+/// some of its files
+///
+/// The loaded book does not remember where its parts come from, as the recipes can
+/// also be taken from the build cache, so the files are read again. The first group of
+/// the pattern, if any, is the span that is shown. Without a match the error points to
+/// the first file, and without files it is returned as it is.
+pub fn find_in_files(
+    files: impl IntoIterator<Item = PathBuf>,
+    pattern: &Regex,
+    err: anyhow::Error,
+) -> anyhow::Error {
+    let mut first = None;
+    for path in files {
+        let Ok(source) = read_to_string(&path) else {
+            first.get_or_insert(path);
+            continue;
+        };
+        let Some(captures) = pattern.captures(&source) else {
+            first.get_or_insert(path);
+            continue;
+        };
+        let found = captures.get(1).or_else(|| captures.get(0)).unwrap();
+        let position = Position {
+            start: point_at(&source, found.start()),
+            end: point_at(&source, found.end()),
+        };
+        let located = Located::new(position, format!("{err:#}"));
+        return Diagnostic::new(path, Some(&source), located.into()).into();
+    }
+    match first {
+        Some(path) => Diagnostic::new(path, None, err).into(),
+        None => err,
+    }
+}
+
+/// The point at the byte `offset` of `source`
+fn point_at(source: &str, offset: usize) -> Point {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Point::new(
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+        offset,
+    )
+}
+
+/// `n` followed by `noun`, in the plural unless `n` is one, like `1 error` or `3 errors`
+#[must_use]
+pub fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{n} {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+/// Remove the position serde_yaml adds to its messages, as it is shown separately
+fn strip_yaml_location(message: &str) -> String {
+    regex_replace_all!(r" at line \d+ column \d+", message, "").into_owned()
+}
+
+/// Turn the errors of a file into [`Diagnostic`]s
+pub trait InFile<T> {
+    fn in_file(self, path: &Path, source: Option<&str>) -> anyhow::Result<T>;
+}
+impl<T> InFile<T> for anyhow::Result<T> {
+    fn in_file(self, path: &Path, source: Option<&str>) -> anyhow::Result<T> {
+        self.map_err(|err| Diagnostic::new(path, source, err).into())
+    }
+}
+
+/// Multiple errors, collected to be reported together
+#[derive(Debug, Default)]
+pub struct Errors(pub Vec<anyhow::Error>);
+impl Errors {
+    /// Add an error, flattening nested collections
+    pub fn push(&mut self, err: anyhow::Error) {
+        match err.downcast::<Errors>() {
+            Ok(Errors(errs)) => self.0.extend(errs),
+            Err(err) => self.0.push(err),
+        }
+    }
+
+    /// Record the error of `res`, if any, and return the value otherwise
+    pub fn collect<T>(&mut self, res: anyhow::Result<T>) -> Option<T> {
+        res.map_err(|err| self.push(err)).ok()
+    }
+
+    /// Returns `value` if no error was collected
+    pub fn into_result<T>(self, value: T) -> anyhow::Result<T> {
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(self.into())
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+impl From<anyhow::Error> for Errors {
+    fn from(value: anyhow::Error) -> Self {
+        let mut errors = Self::default();
+        errors.push(value);
+        errors
+    }
+}
+impl Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for err in &self.0 {
+            writeln!(f, "{err:#}")?;
+            writeln!(f)?;
+        }
+        write!(f, "{} found", count(self.len(), "error"))
+    }
+}
+impl Error for Errors {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use lazy_regex::regex;
+
+    use super::*;
+
+    #[test]
+    fn shows_the_line_and_marks_the_span() {
+        let source = "---\nname: Sugo\ntime: 10x\n---\n";
+        let position = Position::new(3, 7, 23, 3, 10, 26);
+        let err = Located::new(position, "Invalid time").into();
+        let diagnostic = Diagnostic::new("sugo.md", Some(source), err);
+        assert_eq!(
+            diagnostic.to_string(),
+            "Invalid time\n --> sugo.md:3:7\n  |\n3 | time: 10x\n  |       ^^^"
+        );
+    }
+
+    #[test]
+    fn yaml_errors_are_located_in_the_file() {
+        let yaml = "name: Sugo\nserves: molti\n";
+        let err =
+            serde_yaml::from_str::<std::collections::BTreeMap<String, u32>>(yaml).unwrap_err();
+        let start = Point::new(1, 1, 4);
+        let located = Located::yaml(&err, &start);
+        assert_eq!(located.position.start.line, 2);
+        assert!(!located.message.contains("at line"), "{}", located.message);
+    }
+
+    #[test]
+    fn without_a_position_only_the_file_is_shown() {
+        let diagnostic = Diagnostic::new("sugo.md", Some("Sugo"), anyhow!("Broken"));
+        assert_eq!(diagnostic.to_string(), "Broken\n --> sugo.md");
+    }
+
+    #[test]
+    fn found_in_files_at_the_first_group() {
+        let dir = std::env::temp_dir().join(format!("build-book-find-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sugo.md");
+        std::fs::write(&path, "---\ntags:\n  - Ignoto\n---\n").unwrap();
+        let err = find_in_files([path], regex!(r"- (Ignoto)"), anyhow!("Unknown tag"));
+        let _ = std::fs::remove_dir_all(&dir);
+        let message = err.to_string();
+        assert!(message.contains("sugo.md:3:5"), "{message}");
+        assert!(message.ends_with("  |     ^^^^^^"), "{message}");
+    }
+
+    #[test]
+    fn errors_are_flattened_and_counted() {
+        let mut errors = Errors::default();
+        errors.push(anyhow!("Uno"));
+        errors.push(Errors(vec![anyhow!("Due"), anyhow!("Tre")]).into());
+        assert_eq!(errors.len(), 3);
+        assert_eq!(count(errors.len(), "error"), "3 errors");
+        assert_eq!(count(1, "error"), "1 error");
+        assert!(errors.into_result(()).is_err());
+        assert!(Errors::default().into_result(()).is_ok());
+    }
+}
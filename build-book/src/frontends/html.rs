@@ -0,0 +1,681 @@
+//! Static html site generated from the book
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{copy, create_dir_all, metadata, write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use axohtml::{dom::DOMTree, elements::li, html, text, unsafe_text};
+use chrono::Duration;
+use markdown::mdast::{
+    BlockQuote, Code, Delete, Emphasis, Heading, Html, Image, InlineCode, Link, List, ListItem,
+    Node, Paragraph, Root, Strong, Text,
+};
+
+use crate::{
+    book::{
+        assets,
+        cache::{stamp, BuildCache},
+        language::{Label, Section},
+        links,
+        recipe::{Ingredient, Recipe},
+        tags::Tags,
+    },
+    parsers::{markdown::Markdown, title_separated_list::TitleSeparatedList},
+    Book,
+};
+
+const STYLE: &str = r#"body {
+    max-width: 50em;
+    margin: 0 auto;
+    padding: 1em;
+    font-family: serif;
+    line-height: 1.5;
+}
+nav {
+    font-size: small;
+}
+.quantity {
+    font-weight: bold;
+}
+.optional, .comment, .time {
+    font-style: italic;
+}
+.tags li {
+    display: inline;
+    margin-right: 1em;
+}
+img {
+    max-width: 100%;
+}
+.picture {
+    display: block;
+    max-height: 25em;
+    margin: 1em auto;
+}
+.thumbnail {
+    max-width: 4em;
+    max-height: 4em;
+    margin-right: 0.5em;
+    vertical-align: middle;
+}
+"#;
+
+/// Largest side of the thumbnails of the recipe pictures, in pixels
+const THUMBNAIL_SIZE: u32 = 240;
+
+pub fn emit(
+    mut book: Book,
+    output: impl AsRef<Path>,
+    cache: &mut BuildCache,
+) -> anyhow::Result<()> {
+    log::info!("Writing html book to {}", output.as_ref().display());
+    // computed before the links to the recipes used as ingredients are rewritten
+    let total_times: BTreeMap<String, Duration> = book
+        .recipes
+        .iter()
+        .map(|(name, recipe)| (name.clone(), book.total_time(recipe)))
+        .collect();
+    let tools: BTreeMap<String, Vec<Markdown>> = book
+        .recipes
+        .iter()
+        .map(|(name, recipe)| {
+            let tools = book.all_tools(recipe).into_iter().cloned().collect();
+            (name.clone(), tools)
+        })
+        .collect();
+    // all the pages are one level deep
+    links::rewrite(&mut book, |name| format!("../recipes/{name}.html"));
+    let output = output.as_ref();
+    create_dir_all(output).context("Cannot create output dir")?;
+    let output = &output.canonicalize().context("Cannot find output dir")?;
+
+    let images = assets::images(&book).context("While collecting the images")?;
+    match &book.root {
+        Some(root) => {
+            let thumbnails: BTreeSet<PathBuf> = book
+                .recipes
+                .values()
+                .filter_map(assets::recipe_image)
+                .collect();
+            copy_images(root, output, images.keys(), &thumbnails, cache)?;
+        }
+        None if images.is_empty() => {}
+        None => log::warn!("The book was not loaded from a directory, its images are not copied"),
+    }
+    assets::rewrite(&mut book, |path| format!("../images/{}", web_path(path)));
+
+    write(output.join("style.css"), STYLE).context("Cannot write stylesheet")?;
+    cache.record(output, Path::new("style.css"), stamp(&STYLE));
+    write_page(&output.join("index.html"), index(&book)).context("While writing index")?;
+    cache.record(output, Path::new("index.html"), 0);
+
+    create_dir_all(output.join("introduction")).context("Cannot create introduction dir")?;
+    for (name, title, content) in introduction_sections(&book) {
+        log::debug!("Writing introduction page {name}");
+        let file = PathBuf::from(format!("introduction/{name}.html"));
+        write_page(&output.join(&file), page(&book, title, "../", content))
+            .with_context(|| format!("While writing introduction page {name}"))?;
+        cache.record(output, &file, 0);
+    }
+
+    create_dir_all(output.join("recipes")).context("Cannot create recipes dir")?;
+    for (name, recipe) in &book.recipes {
+        let file = PathBuf::from(format!("recipes/{name}.html"));
+        // everything the page is generated from, with the links already rewritten
+        let page_stamp = stamp(&(
+            &book.front_matter.title,
+            recipe,
+            total_times[name].num_seconds(),
+            &tools[name],
+            &book.tags,
+        ));
+        if cache.is_fresh(output, &file, page_stamp) && output.join(&file).exists() {
+            log::debug!("Recipe page {name} is up to date");
+            continue;
+        }
+        log::debug!("Writing recipe page {name}");
+        write_page(
+            &output.join(&file),
+            page(
+                &book,
+                &recipe.name,
+                "../",
+                recipe_content(&book, recipe, total_times[name], &tools[name]),
+            ),
+        )
+        .with_context(|| format!("While writing recipe {name}"))?;
+        cache.record(output, &file, page_stamp);
+    }
+
+    if let Some(tags) = &book.tags {
+        create_dir_all(output.join("tags")).context("Cannot create tags dir")?;
+        for (name, tag) in &tags.0 {
+            log::debug!("Writing tag page {name}");
+            let file = PathBuf::from(format!("tags/{}.html", tags.slug(name)));
+            write_page(
+                &output.join(&file),
+                page(&book, &tag.name, "../", tag_content(&book, tags, name)),
+            )
+            .with_context(|| format!("While writing tag {name}"))?;
+            cache.record(output, &file, 0);
+        }
+    }
+
+    cache.remove_stale(output)
+}
+
+/// Copy the images from the book in `root` to `images/` in the output, writing the
+/// thumbnails of the ones in `thumbnails` to `thumbnails/`
+fn copy_images<'p>(
+    root: &Path,
+    output: &Path,
+    images: impl Iterator<Item = &'p PathBuf>,
+    thumbnails: &BTreeSet<PathBuf>,
+    cache: &mut BuildCache,
+) -> anyhow::Result<()> {
+    for path in images {
+        let source = root.join(path);
+        let source_stamp = metadata(&source)
+            .map(|metadata| stamp(&(metadata.len(), metadata.modified().ok())))
+            .with_context(|| format!("Cannot find image `{}`", path.display()))?;
+
+        let file = Path::new("images").join(path);
+        if !(cache.is_fresh(output, &file, source_stamp) && output.join(&file).exists()) {
+            log::debug!("Copying image {}", path.display());
+            create_dir_all(output.join(&file).parent().unwrap())
+                .context("Cannot create images dir")?;
+            copy(&source, output.join(&file))
+                .with_context(|| format!("Cannot copy image `{}`", path.display()))?;
+            cache.record(output, &file, source_stamp);
+        }
+
+        if !thumbnails.contains(path) {
+            continue;
+        }
+        let file = Path::new("thumbnails").join(path);
+        let thumbnail_stamp = stamp(&(source_stamp, THUMBNAIL_SIZE));
+        if cache.is_fresh(output, &file, thumbnail_stamp) && output.join(&file).exists() {
+            continue;
+        }
+        log::debug!("Writing thumbnail of image {}", path.display());
+        create_dir_all(output.join(&file).parent().unwrap())
+            .context("Cannot create thumbnails dir")?;
+        if let Err(err) = write_thumbnail(&source, &output.join(&file)) {
+            log::warn!(
+                "Cannot resize image `{}`, it is used whole as thumbnail: {err:#}",
+                path.display()
+            );
+            copy(&source, output.join(&file))
+                .with_context(|| format!("Cannot copy image `{}`", path.display()))?;
+        }
+        cache.record(output, &file, thumbnail_stamp);
+    }
+    Ok(())
+}
+
+/// Write the image at `source` shrunk to fit in the thumbnail size, in the format
+/// given by the extension of `target`
+fn write_thumbnail(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let image = image::open(source).context("Cannot read image")?;
+    if image.width() <= THUMBNAIL_SIZE && image.height() <= THUMBNAIL_SIZE {
+        copy(source, target).context("Cannot copy image")?;
+        return Ok(());
+    }
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .save(target)
+        .context("Cannot write thumbnail")
+}
+
+/// A path relative to the book as the url of a file in the output
+fn web_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The thumbnail of the picture of a recipe, for the lists of recipes
+fn thumbnail_html(recipe: &Recipe, root: &str) -> String {
+    assets::recipe_image(recipe)
+        .map(|path| {
+            format!(
+                "<img class=\"thumbnail\" src=\"{root}thumbnails/{}\" alt=\"\"/>",
+                escape(&web_path(&path))
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn write_page(path: &Path, page: DOMTree<String>) -> anyhow::Result<()> {
+    write(path, format!("<!DOCTYPE html>\n{page}")).context("Cannot write file")
+}
+
+/// Wrap some content in a full html page
+fn page(book: &Book, title: &Markdown, root: &str, content: String) -> DOMTree<String> {
+    let stylesheet = format!("{root}style.css");
+    let index = format!("{root}index.html");
+    html!(
+        <html>
+            <head>
+                <title>{ text!("{} - {}", title.0.to_string(), book.front_matter.title.0.to_string()) }</title>
+                <meta charset="utf-8"/>
+                <link rel="stylesheet" href=stylesheet/>
+            </head>
+            <body>
+                <nav>
+                    <a href=index>
+                        { unsafe_text!(inline_html(&book.front_matter.title.0)) }
+                    </a>
+                </nav>
+                <main>
+                    <h1>{ unsafe_text!(inline_html(&title.0)) }</h1>
+                    { unsafe_text!(content) }
+                </main>
+            </body>
+        </html>
+    )
+}
+
+fn index(book: &Book) -> DOMTree<String> {
+    let front_matter = &book.front_matter;
+    let label = |label| front_matter.language.label(label);
+    let mailto = format!("mailto:{}", front_matter.email);
+    let site = front_matter.site.to_string();
+    let introduction: Vec<Box<li<String>>> = introduction_sections(book)
+        .into_iter()
+        .map(|(name, title, _)| {
+            let href = format!("introduction/{name}.html");
+            html!(
+                <li>
+                    <a href=href>
+                        { unsafe_text!(inline_html(&title.0)) }
+                    </a>
+                </li>
+            )
+        })
+        .collect();
+    let recipes: Vec<Box<li<String>>> = book
+        .recipes
+        .iter()
+        .map(|(name, recipe)| {
+            let href = format!("recipes/{name}.html");
+            html!(
+                <li>
+                    { unsafe_text!(thumbnail_html(recipe, "")) }
+                    <a href=href>
+                        { unsafe_text!(inline_html(&recipe.name.0)) }
+                    </a>
+                </li>
+            )
+        })
+        .collect();
+    let tags = match &book.tags {
+        Some(tags) => format!(
+            "<h2>{}</h2>{}",
+            label(Label::Tags),
+            tag_tree(tags, tags.roots(), "")
+        ),
+        None => String::new(),
+    };
+    html!(
+        <html>
+            <head>
+                <title>{ text!(front_matter.title.0.to_string()) }</title>
+                <meta charset="utf-8"/>
+                <link rel="stylesheet" href="style.css"/>
+            </head>
+            <body>
+                <header>
+                    <h1>{ unsafe_text!(inline_html(&front_matter.title.0)) }</h1>
+                    <p class="subtitle">{ unsafe_text!(inline_html(&front_matter.subtitle.0)) }</p>
+                    <p class="author">
+                        <a href=mailto>{ text!(&front_matter.author) }</a>
+                        " - "
+                        <a href=site>{ text!(front_matter.site.to_string()) }</a>
+                    </p>
+                </header>
+                <main>
+                    <h2>{ text!(label(Label::Introduction)) }</h2>
+                    <ul>{ introduction }</ul>
+                    <h2>{ text!(label(Label::Recipes)) }</h2>
+                    <ul>{ recipes }</ul>
+                    { unsafe_text!(tags) }
+                </main>
+            </body>
+        </html>
+    )
+}
+
+/// List the introduction pages, as `(file name, title, html content)`
+fn introduction_sections(book: &Book) -> [(&'static str, &Markdown, String); 4] {
+    let intro = &book.introduction;
+    [
+        (
+            "zen",
+            &intro.zen.metas.title,
+            sections_html(&intro.zen.content),
+        ),
+        (
+            "prologue",
+            &intro.prologue.metas.title,
+            block_html(&intro.prologue.content.0),
+        ),
+        (
+            "warnings",
+            &intro.warnings.metas.title,
+            sections_html(&intro.warnings.content),
+        ),
+        (
+            "thanks",
+            &intro.thanks.metas.title,
+            block_html(&intro.thanks.content.0),
+        ),
+    ]
+}
+
+fn sections_html(list: &TitleSeparatedList<Markdown, Markdown>) -> String {
+    let mut out = String::new();
+    for item in &list.items {
+        out.push_str("<section><h2>");
+        out.push_str(&inline_html(&item.title.0));
+        out.push_str("</h2>");
+        out.push_str(&block_html(&item.content.0));
+        out.push_str("</section>");
+    }
+    out
+}
+
+/// The page of `recipe`, that takes `total_time` and needs `tools` together with the
+/// recipes it uses
+fn recipe_content(
+    book: &Book,
+    recipe: &Recipe,
+    total_time: Duration,
+    tools: &[Markdown],
+) -> String {
+    let language = book.front_matter.language;
+    let label = |label| language.label(label);
+    let mut out = String::new();
+
+    if let Some(path) = assets::recipe_image(recipe) {
+        out.push_str(&format!(
+            "<img class=\"picture\" src=\"../images/{}\" alt=\"\"/>",
+            escape(&web_path(&path))
+        ));
+    }
+    out.push_str(&block_html(&recipe.descr.0));
+    let mut time = format!(
+        "{}: {}",
+        label(Label::Time),
+        format_duration(recipe.time.total())
+    );
+    let parts: Vec<_> = [
+        (label(Label::PrepTime), recipe.time.prep()),
+        (label(Label::CookTime), recipe.time.cook()),
+        (label(Label::RestTime), recipe.time.rest()),
+    ]
+    .into_iter()
+    .filter_map(|(part, time)| Some(format!("{part} {}", format_duration(time?))))
+    .collect();
+    if !parts.is_empty() {
+        time.push_str(&format!(" ({})", parts.join(", ")));
+    }
+    if total_time != recipe.time.total() {
+        time.push_str(&format!(
+            ", {} {}",
+            format_duration(total_time),
+            label(Label::WithSubrecipes)
+        ));
+    }
+    out.push_str(&format!("<p class=\"time\">{time}</p>"));
+
+    out.push_str(&format!(
+        "<h2>{}</h2><ul class=\"ingredients\">",
+        label(Label::Ingredients)
+    ));
+    for ingredient in &recipe.ingredients {
+        out.push_str(&ingredient_html(ingredient, label(Label::Optional)));
+    }
+    out.push_str("</ul>");
+
+    out.push_str(&format!(
+        "<h2>{}</h2><ul class=\"tools\">",
+        label(Label::Tools)
+    ));
+    for tool in tools {
+        out.push_str(&format!("<li>{}</li>", inline_html(&tool.0)));
+    }
+    out.push_str("</ul>");
+
+    out.push_str(&format!(
+        "<h2>{}</h2><ol class=\"steps\">",
+        language.heading(Section::Preparation)
+    ));
+    for step in &recipe.preparazione {
+        out.push_str(&format!("<li>{}</li>", inline_html(&step.text.0)));
+    }
+    out.push_str("</ol>");
+
+    if !recipe.modifiche_e_aggiunte.is_empty() {
+        out.push_str(&format!(
+            "<h2>{}</h2><ul class=\"variations\">",
+            language.heading(Section::Variations)
+        ));
+        for variation in &recipe.modifiche_e_aggiunte {
+            out.push_str(&format!("<li>{}</li>", inline_html(&variation.0)));
+        }
+        out.push_str("</ul>");
+    }
+
+    out.push_str("<ul class=\"tags\">");
+    for tag in &recipe.tags {
+        match &book.tags {
+            Some(tags) => out.push_str(&format!("<li>{}</li>", tag_link(tags, tag, "../"))),
+            None => out.push_str(&format!("<li>#{}</li>", escape(tag))),
+        }
+    }
+    out.push_str("</ul>");
+
+    out
+}
+
+fn tag_content(book: &Book, tags: &Tags, name: &str) -> String {
+    let label = |label| book.front_matter.language.label(label);
+    let mut out = String::new();
+    let tag = &tags.0[name];
+
+    if let Some(description) = &tag.description {
+        out.push_str(&block_html(&description.0));
+    }
+    if let Some(parent) = &tag.parent {
+        out.push_str(&format!(
+            "<p class=\"parent\">{}: {}</p>",
+            label(Label::PartOf),
+            tag_link(tags, parent, "../")
+        ));
+    }
+
+    if tags.children(name).next().is_some() {
+        out.push_str(&format!("<h2>{}</h2>", label(Label::Subtags)));
+        out.push_str(&tag_tree(tags, tags.children(name), "../"));
+    }
+
+    out.push_str(&format!("<h2>{}</h2><ul>", label(Label::Recipes)));
+    for (recipe_name, recipe) in &book.recipes {
+        if tags.is_tagged(recipe, name) {
+            out.push_str(&format!(
+                "<li>{}<a href=\"../recipes/{}.html\">{}</a></li>",
+                thumbnail_html(recipe, "../"),
+                escape(recipe_name),
+                inline_html(&recipe.name.0)
+            ));
+        }
+    }
+    out.push_str("</ul>");
+
+    out
+}
+
+/// Nested list of links to `names` and all their descendants
+fn tag_tree<'t>(tags: &'t Tags, names: impl Iterator<Item = &'t str>, root: &str) -> String {
+    let mut out = "<ul class=\"tag-tree\">".to_owned();
+    for name in names {
+        out.push_str(&format!("<li>{}", tag_link(tags, name, root)));
+        if tags.children(name).next().is_some() {
+            out.push_str(&tag_tree(tags, tags.children(name), root));
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ul>");
+    out
+}
+
+fn tag_link(tags: &Tags, name: &str, root: &str) -> String {
+    let title = tags
+        .0
+        .get(name)
+        .map_or_else(|| escape(name), |tag| inline_html(&tag.name.0));
+    format!(
+        "<a href=\"{root}tags/{}.html\">{title}</a>",
+        escape(&tags.slug(name))
+    )
+}
+
+/// An item of the ingredient list, marking the optional ones with `optional`
+fn ingredient_html(ingredient: &Ingredient, optional: &str) -> String {
+    let mut out = format!(
+        "<li>{} <span class=\"quantity\">{}</span>",
+        inline_html(&ingredient.name().0),
+        escape(&ingredient.quantity().to_string())
+    );
+    if let Some(comment) = ingredient.comment() {
+        out.push_str(&format!(
+            " <span class=\"comment\">({})</span>",
+            inline_html(&comment.0)
+        ));
+    }
+    if ingredient.is_optional() {
+        out.push_str(&format!(" <span class=\"optional\">({optional})</span>"));
+    }
+    out.push_str("</li>");
+    out
+}
+
+fn format_duration(duration: Duration) -> String {
+    escape(&humantime::format_duration(duration.to_std().unwrap_or_default()).to_string())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render markdown that is expected to fit in a line, dropping the wrapping paragraph
+fn inline_html(node: &Node) -> String {
+    match node {
+        Node::Root(Root { children, .. }) | Node::ListItem(ListItem { children, .. })
+            if children.len() == 1 =>
+        {
+            inline_html(&children[0])
+        }
+        Node::Root(Root { children, .. }) | Node::ListItem(ListItem { children, .. }) => {
+            children_html(children)
+        }
+        Node::Paragraph(Paragraph { children, .. }) => children_html(children),
+        node => block_html(node),
+    }
+}
+
+fn children_html(children: &[Node]) -> String {
+    children.iter().map(block_html).collect()
+}
+
+/// Render a markdown tree as html
+fn block_html(node: &Node) -> String {
+    match node {
+        Node::Root(Root { children, .. }) => children_html(children),
+        Node::Paragraph(Paragraph { children, .. }) => {
+            format!("<p>{}</p>", children_html(children))
+        }
+        Node::Heading(Heading {
+            children, depth, ..
+        }) => format!("<h{depth}>{}</h{depth}>", children_html(children)),
+        Node::BlockQuote(BlockQuote { children, .. }) => {
+            format!("<blockquote>{}</blockquote>", children_html(children))
+        }
+        Node::List(List {
+            children,
+            ordered: true,
+            start,
+            ..
+        }) => format!(
+            "<ol start=\"{}\">{}</ol>",
+            start.unwrap_or(1),
+            children_html(children)
+        ),
+        Node::List(List { children, .. }) => format!("<ul>{}</ul>", children_html(children)),
+        Node::ListItem(ListItem {
+            children, spread, ..
+        }) => {
+            if *spread {
+                format!("<li>{}</li>", children_html(children))
+            } else {
+                // tight lists do not wrap their paragraphs
+                format!(
+                    "<li>{}</li>",
+                    children.iter().map(inline_html).collect::<String>()
+                )
+            }
+        }
+        Node::Text(Text { value, .. }) => escape(value),
+        Node::Emphasis(Emphasis { children, .. }) => {
+            format!("<em>{}</em>", children_html(children))
+        }
+        Node::Strong(Strong { children, .. }) => {
+            format!("<strong>{}</strong>", children_html(children))
+        }
+        Node::Delete(Delete { children, .. }) => format!("<del>{}</del>", children_html(children)),
+        Node::InlineCode(InlineCode { value, .. }) => format!("<code>{}</code>", escape(value)),
+        Node::Code(Code { value, .. }) => format!("<pre><code>{}</code></pre>", escape(value)),
+        Node::Break(_) => "<br/>".to_owned(),
+        Node::ThematicBreak(_) => "<hr/>".to_owned(),
+        Node::Link(Link {
+            children,
+            url,
+            title,
+            ..
+        }) => format!(
+            "<a href=\"{}\"{}>{}</a>",
+            escape(url),
+            title
+                .as_ref()
+                .map(|title| format!(" title=\"{}\"", escape(title)))
+                .unwrap_or_default(),
+            children_html(children)
+        ),
+        Node::Image(Image {
+            alt, url, title, ..
+        }) => format!(
+            "<img src=\"{}\" alt=\"{}\"{}/>",
+            escape(url),
+            escape(alt),
+            title
+                .as_ref()
+                .map(|title| format!(" title=\"{}\"", escape(title)))
+                .unwrap_or_default(),
+        ),
+        Node::Html(Html { value, .. }) => value.clone(),
+        node => {
+            log::warn!("Markdown node not supported by the html frontend: {node:?}");
+            node.children()
+                .map(|c| children_html(c))
+                .unwrap_or_default()
+        }
+    }
+}
@@ -9,14 +9,20 @@
 pub mod book;
 pub use book::Book;
 
+pub mod diagnostic;
+
 pub(crate) mod parsers;
 
 pub mod frontends {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     use clap::Subcommand;
 
-    use crate::Book;
+    use crate::{
+        book::{cache::BuildCache, language::Language, lint::lint, shopping::Request, translation},
+        diagnostic::count,
+        Book,
+    };
 
     #[cfg(feature = "frontend-yaml")]
     pub mod yaml {
@@ -24,35 +30,102 @@ pub mod frontends {
 
         use anyhow::Context;
 
-        use crate::Book;
+        use crate::{book::links, Book};
 
-        pub fn emit(book: Book, output: impl AsRef<Path>) -> anyhow::Result<()> {
+        pub fn emit(mut book: Book, output: impl AsRef<Path>) -> anyhow::Result<()> {
             log::info!("Writing yaml book to {}", output.as_ref().display());
+            links::rewrite(&mut book, |name| format!("#recipes/{name}"));
             serde_yaml::to_writer(File::create(output).context("Cannot create file")?, &book)
                 .context("While writing file")?;
             Ok(())
         }
     }
 
-    #[cfg(feature = "frontend-html")]
-    pub mod html {
-        use std::{
-            fs::{create_dir_all, File},
-            path::Path,
-        };
+    #[cfg(feature = "frontend-json")]
+    pub mod json {
+        use std::{fs::File, path::Path};
 
         use anyhow::Context;
+        use schemars::schema_for;
+
+        use crate::{book::links, Book};
+
+        pub fn emit(
+            mut book: Book,
+            output: impl AsRef<Path>,
+            schema: Option<&Path>,
+        ) -> anyhow::Result<()> {
+            log::info!("Writing json book to {}", output.as_ref().display());
+            links::rewrite(&mut book, |name| format!("#recipes/{name}"));
+            serde_json::to_writer_pretty(
+                File::create(output).context("Cannot create file")?,
+                &book,
+            )
+            .context("While writing file")?;
+            if let Some(schema) = schema {
+                log::info!("Writing json schema to {}", schema.display());
+                serde_json::to_writer_pretty(
+                    File::create(schema).context("Cannot create schema file")?,
+                    &schema_for!(Book),
+                )
+                .context("While writing schema")?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "frontend-html")]
+    pub mod html;
+
+    #[cfg(feature = "frontend-markdown")]
+    pub mod markdown {
+        use std::path::Path;
 
         use crate::Book;
 
         pub fn emit(book: Book, output: impl AsRef<Path>) -> anyhow::Result<()> {
-            log::info!("Writing html book to {}", output.as_ref().display());
-            create_dir_all(output.as_ref()).context("Cannot create output dir")?;
+            log::info!("Writing markdown book to {}", output.as_ref().display());
+            book.regenerate(output)
+        }
+    }
+
+    pub mod shopping_list {
+        use std::{fs::write, path::Path};
+
+        use anyhow::Context;
+
+        use crate::{
+            book::shopping::{Request, ShoppingList},
+            Book,
+        };
+
+        pub fn emit(book: Book, recipes: &[Request], output: Option<&Path>) -> anyhow::Result<()> {
+            let list = ShoppingList::new(&book, recipes).context("While building the list")?;
+            match output {
+                Some(output) => {
+                    log::info!("Writing shopping list to {}", output.display());
+                    write(output, list.to_string()).context("Cannot write file")?;
+                }
+                None => print!("{list}"),
+            }
             Ok(())
         }
     }
 
-    #[derive(Debug, Subcommand)]
+    /// Which languages of the book are emitted
+    #[derive(Debug, Clone, Copy, Default)]
+    pub enum Languages {
+        /// The whole book, with the translations in the outputs that can hold them
+        #[default]
+        Book,
+        /// A single language, using the main one for what is not translated
+        One(Language),
+        /// Each language in its own output. The outputs of the translations have the
+        /// code of the language before the extension, like `book.en.yml`
+        All,
+    }
+
+    #[derive(Debug, Clone, Subcommand)]
     pub enum Frontend {
         /// Create a YAML representation of the book
         #[cfg(feature = "frontend-yaml")]
@@ -60,27 +133,140 @@ pub mod frontends {
             /// Path to the output
             output: PathBuf,
         },
+        /// Create a JSON representation of the book
+        #[cfg(feature = "frontend-json")]
+        Json {
+            /// Path to the output
+            output: PathBuf,
+            /// Also write the JSON Schema of the output to this path
+            #[arg(long)]
+            schema: Option<PathBuf>,
+        },
+        /// Write the book back as a markdown source directory
+        #[cfg(feature = "frontend-markdown")]
+        Markdown {
+            /// Path to the output directory
+            output: PathBuf,
+        },
         /// Create a static html representation of the book
         #[cfg(feature = "frontend-html")]
         Html {
             /// Path to the output directory
             output: PathBuf,
         },
+        /// Create a shopping list merging the ingredients of some recipes
+        ShoppingList {
+            /// Recipes to shop for, as `name` or `name:servings`
+            #[arg(required = true)]
+            recipes: Vec<Request>,
+            /// Path to the output. If missing, the list is printed on stdout
+            #[arg(long, short)]
+            output: Option<PathBuf>,
+        },
         /// Only checks for errors
         Check,
     }
     impl Frontend {
-        pub fn emit(&self, book: Book) -> anyhow::Result<()> {
+        /// Emit the book, skipping the outputs that the cache shows are up to date
+        #[cfg_attr(not(feature = "frontend-html"), allow(unused_variables))]
+        pub fn emit(&self, book: Book, cache: &mut BuildCache) -> anyhow::Result<()> {
             match self {
                 #[cfg(feature = "frontend-yaml")]
                 Frontend::Yaml { output } => yaml::emit(book, output),
+                #[cfg(feature = "frontend-json")]
+                Frontend::Json { output, schema } => json::emit(book, output, schema.as_deref()),
+                #[cfg(feature = "frontend-markdown")]
+                Frontend::Markdown { output } => markdown::emit(book, output),
                 #[cfg(feature = "frontend-html")]
-                Frontend::Html { output } => html::emit(book, output),
+                Frontend::Html { output } => html::emit(book, output, cache),
+                Frontend::ShoppingList { recipes, output } => {
+                    shopping_list::emit(book, recipes, output.as_deref())
+                }
                 Frontend::Check => {
+                    // the links and the images were checked with the rest while loading
+                    let warnings = lint(&book);
+                    for warning in &warnings {
+                        log::warn!("{:#}", warning.in_source(book.root.as_deref()))
+                    }
+                    let stale = translation::stale(&book);
+                    for stale in &stale {
+                        log::warn!("{stale}")
+                    }
+                    if !warnings.is_empty() || !stale.is_empty() {
+                        log::warn!("Found {}", count(warnings.len() + stale.len(), "warning"))
+                    }
                     log::info!("Book builded successfully!");
                     Ok(())
                 }
             }
         }
+
+        /// Emit the book in the selected languages
+        pub fn emit_languages(
+            &self,
+            book: Book,
+            languages: Languages,
+            cache: &mut BuildCache,
+        ) -> anyhow::Result<()> {
+            match languages {
+                Languages::Book => self.emit(book, cache),
+                Languages::One(language) => self.emit(book.translated(language), cache),
+                Languages::All => {
+                    for language in book.languages() {
+                        log::info!("Writing the book in `{language}`");
+                        let frontend = if language == book.front_matter.language {
+                            self.clone()
+                        } else {
+                            self.localized(language)
+                        };
+                        frontend.emit(book.translated(language), cache)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+
+        /// The same frontend, writing to the outputs of a translation
+        fn localized(&self, language: Language) -> Self {
+            let localized = |output: &Path| {
+                let code = language.code();
+                match (output.file_stem(), output.extension()) {
+                    (Some(stem), Some(extension)) => output.with_file_name(format!(
+                        "{}.{code}.{}",
+                        stem.to_string_lossy(),
+                        extension.to_string_lossy()
+                    )),
+                    _ => {
+                        let mut output = output.as_os_str().to_owned();
+                        output.push(format!(".{code}"));
+                        output.into()
+                    }
+                }
+            };
+            match self {
+                #[cfg(feature = "frontend-yaml")]
+                Frontend::Yaml { output } => Frontend::Yaml {
+                    output: localized(output),
+                },
+                #[cfg(feature = "frontend-json")]
+                Frontend::Json { output, schema } => Frontend::Json {
+                    output: localized(output),
+                    schema: schema.clone(),
+                },
+                #[cfg(feature = "frontend-markdown")]
+                Frontend::Markdown { output } => Frontend::Markdown {
+                    output: localized(output),
+                },
+                #[cfg(feature = "frontend-html")]
+                Frontend::Html { output } => Frontend::Html {
+                    output: localized(output),
+                },
+                Frontend::ShoppingList { recipes, output } => Frontend::ShoppingList {
+                    recipes: recipes.clone(),
+                    output: output.as_deref().map(localized),
+                },
+                Frontend::Check => Frontend::Check,
+            }
+        }
     }
 }
@@ -1,22 +1,79 @@
-use std::path::PathBuf;
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use simple_logger::SimpleLogger;
 
-use build_book::{frontends::Frontend, Book};
+use build_book::{
+    book::{
+        cache::{self, BuildCache},
+        format::format_book,
+        language::Language,
+        schema::write_schemas,
+        watch::WatchedBook,
+    },
+    diagnostic::{count, Errors},
+    frontends::{Frontend, Languages},
+    Book,
+};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Path to the book directory
     input: PathBuf,
-    /// What frontend to use
+    /// Scale all the recipes to this number of servings
+    #[arg(long, short)]
+    servings: Option<NonZeroU32>,
+    /// Keep running, writing the output again whenever a source file changes
+    #[arg(long, short)]
+    watch: bool,
+    /// Build everything, without reusing the results of the previous builds
+    #[arg(long)]
+    no_cache: bool,
+    /// Directory where the results of the builds are kept. Defaults to one for each
+    /// book in the temporary directory of the system, out of the book sources
+    #[arg(long, conflicts_with = "no_cache")]
+    cache_dir: Option<PathBuf>,
+    /// Emit the book in this language, using the main one for what is not translated
+    #[arg(long, short)]
+    language: Option<Language>,
+    /// Emit each language of the book, adding the language code to the outputs of the
+    /// translations, like `book.en.yml`
+    #[arg(long, conflicts_with = "language")]
+    all_languages: bool,
+    /// What to do with the book
     #[command(subcommand)]
-    frontend: Frontend,
+    command: Command,
 }
 
-fn main() -> anyhow::Result<()> {
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Frontend(Frontend),
+    /// Rewrite the source files in a canonical layout
+    Fmt {
+        /// Only report the files that are not formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Write the JSON Schemas of the yaml headers, so editors can validate them
+    Schemas {
+        /// Directory to write the schemas into. Defaults to `schemas` inside the book
+        output: Option<PathBuf>,
+    },
+    /// Regenerate the source files from a yaml book, like the one written by the yaml frontend
+    ImportYaml {
+        /// Path to the yaml book
+        book: PathBuf,
+    },
+}
+
+fn main() -> anyhow::Result<ExitCode> {
     SimpleLogger::new()
         .with_colors(true)
         .with_level(log::LevelFilter::Info)
@@ -24,11 +81,132 @@ fn main() -> anyhow::Result<()> {
         .env()
         .init()
         .context("Cannot init logger")?;
-    let Args { frontend, input } = Parser::parse();
+    let Args {
+        command,
+        input,
+        servings,
+        watch,
+        no_cache,
+        cache_dir,
+        language,
+        all_languages,
+    } = Parser::parse();
+    let languages = match (language, all_languages) {
+        (Some(language), _) => Languages::One(language),
+        (None, true) => Languages::All,
+        (None, false) => Languages::Book,
+    };
+    let frontend = match command {
+        Command::Frontend(frontend) => frontend,
+        _ if watch => {
+            log::error!("`--watch` can be used only with the frontends");
+            return Ok(ExitCode::FAILURE);
+        }
+        Command::Fmt { check } => return Ok(fmt(&input, check)),
+        Command::ImportYaml { book } => return Ok(import_yaml(&book, &input)),
+        Command::Schemas { output } => {
+            write_schemas(output.unwrap_or_else(|| input.join("schemas")))?;
+            return Ok(ExitCode::SUCCESS);
+        }
+    };
+    let mut cache = if no_cache {
+        BuildCache::disabled()
+    } else {
+        BuildCache::open(cache_dir.unwrap_or_else(|| cache::default_dir(&input)))
+    };
+    if watch {
+        watch_book(&input, servings, &frontend, languages, &mut cache)
+    }
     log::info!("Reading book");
-    let book = Book::load(input)?;
+    let mut book = match Book::load_cached(&input, &mut cache) {
+        Ok(book) => book,
+        Err(err) => {
+            report(err.into());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if let Some(servings) = servings {
+        log::info!("Scaling recipes to {servings} servings");
+        book.scale(servings);
+    }
     log::info!("Writing output");
-    frontend.emit(book)?;
+    if let Err(err) = frontend.emit_languages(book, languages, &mut cache) {
+        report(err.into());
+        return Ok(ExitCode::FAILURE);
+    }
+    save_cache(&cache);
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Format the book sources, failing in `check` mode if some are not formatted
+fn fmt(input: &Path, check: bool) -> ExitCode {
+    match format_book(input, check) {
+        Ok(changed) if check && !changed.is_empty() => {
+            log::error!("Found {} not formatted", count(changed.len(), "file"));
+            ExitCode::FAILURE
+        }
+        Ok(changed) => {
+            log::info!("{} formatted", count(changed.len(), "file"));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            report(err.into());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Write the sources of a yaml book into `output`
+fn import_yaml(book: &Path, output: &Path) -> ExitCode {
+    match Book::load_yaml(book).and_then(|book| book.regenerate(output)) {
+        Ok(()) => {
+            log::info!("Book imported successfully!");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            report(err.into());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Emit the book again each time its sources change, reloading only the changed files
+fn watch_book(
+    input: &Path,
+    servings: Option<NonZeroU32>,
+    frontend: &Frontend,
+    languages: Languages,
+    cache: &mut BuildCache,
+) -> ! {
+    let mut sources = WatchedBook::load(input);
+    loop {
+        let emitted = sources.book().and_then(|mut book| {
+            if let Some(servings) = servings {
+                book.scale(servings);
+            }
+            frontend.emit_languages(book, languages, cache)
+        });
+        match emitted {
+            Ok(()) => save_cache(cache),
+            Err(err) => report(err.into()),
+        }
+        log::info!("Waiting for changes");
+        sources.wait_changes();
+    }
+}
+
+/// Save the cache for the next build. Failing only makes the next build slower
+fn save_cache(cache: &BuildCache) {
+    if let Err(err) = cache.save() {
+        log::warn!("Cannot save the build cache: {err:#}")
+    }
+}
 
-    Ok(())
+/// Print all the errors found, with their count
+fn report(errors: Errors) {
+    for err in &errors.0 {
+        log::error!("{err:#}\n");
+    }
+    log::error!("Found {}", count(errors.len(), "error"));
 }
@@ -1,12 +1,17 @@
 //! Markdown file headed with a yaml header
 
 use anyhow::Context;
-use markdown::mdast::{Node, Root, Yaml};
+use markdown::{
+    mdast::{Node, Root, Yaml},
+    unist::Point,
+};
+use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use super::{DisplayMd, FromMd};
+use crate::diagnostic::Located;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub struct HeadedMarkdown<Metas, Content> {
     pub metas: Metas,
     pub content: Content,
@@ -18,19 +23,24 @@ where
     C: FromMd,
 {
     fn parse(mut md: Node) -> anyhow::Result<Self> {
+        let missing = Located::at(&md, "Cannot find yaml metadata");
         let metas = md
             .children_mut()
             .unwrap()
             .drain_filter(|node| matches!(node, markdown::mdast::Node::Yaml(_)))
             .map(|node| {
-                if let Node::Yaml(Yaml { value, .. }) = node {
-                    serde_yaml::from_str::<M>(&value).context("Cannot parse metadata")
+                if let Node::Yaml(Yaml { value, position }) = node {
+                    let start = position.map_or_else(|| Point::new(1, 1, 0), |p| p.start);
+                    serde_yaml::from_str::<M>(&value)
+                        .map_err(|err| Located::yaml(&err, &start))
+                        .context("Cannot parse metadata")
                 } else {
                     unreachable!()
                 }
             })
             .next()
-            .context("Cannot find yaml metadata")
+            .ok_or(missing)
+            .map_err(anyhow::Error::from)
             .flatten()?;
         let content = C::parse(md).context("Cannot parse content")?;
         Ok(Self { metas, content })
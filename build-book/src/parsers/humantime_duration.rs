@@ -1,5 +1,6 @@
 use anyhow::Context;
 use chrono::Duration;
+use schemars::{gen::SchemaGenerator, schema::Schema};
 use serde::{de::Error as DeError, ser::Error as SerError, Deserializer, Serializer};
 
 pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
@@ -13,6 +14,13 @@ where
     humantime_serde::serialize(&std, serializer)
 }
 
+/// Schema of a duration in the humantime format
+pub fn schema(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = gen.subschema_for::<String>().into_object();
+    schema.metadata().description = Some("A duration, like `25m` or `1h 30m`".to_owned());
+    schema.into()
+}
+
 pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
@@ -22,3 +30,33 @@ where
         .context("A duration too big was deserialized")
         .map_err(D::Error::custom)?)
 }
+
+/// The same format, for optional durations
+pub mod option {
+    use chrono::Duration;
+    use schemars::{gen::SchemaGenerator, schema::Schema};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => super::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn schema(gen: &mut SchemaGenerator) -> Schema {
+        super::schema(gen)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super")] Duration);
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(duration)| duration))
+    }
+}
@@ -2,7 +2,16 @@
 
 use std::borrow::Cow;
 
-use markdown::mdast::Node;
+use lazy_regex::regex_is_match;
+use markdown::{
+    mdast::{
+        BlockQuote, Code, Definition, Delete, Emphasis, Heading, Html, Image, ImageReference,
+        InlineCode, Link, LinkReference, List, ListItem, Node, Paragraph, ReferenceKind, Root,
+        Strong, Text, Yaml,
+    },
+    Constructs, ParseOptions,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
 
 use super::{DisplayMd, FromMd};
@@ -19,12 +28,28 @@ impl DisplayMd for Markdown {
         Ok(self.0.clone())
     }
 }
+/// Serialized as markdown source, so the yaml and json books keep the emphasis, links
+/// and images of the texts and can be loaded back.
+///
+/// Before, the texts were serialized with `to_string`, that keeps only the plain text:
+/// readers of the yaml and json books now get `_pomodoro_` where they got `pomodoro`
 impl Serialize for Markdown {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        self.fmt().unwrap().to_string().serialize(serializer)
+        stringify(&self.fmt().unwrap()).serialize(serializer)
+    }
+}
+impl JsonSchema for Markdown {
+    fn schema_name() -> String {
+        "Markdown".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some("Markdown source".to_owned());
+        schema.into()
     }
 }
 impl<'de> Deserialize<'de> for Markdown {
@@ -38,3 +63,300 @@ impl<'de> Deserialize<'de> for Markdown {
         Ok(Self::parse(md).unwrap())
     }
 }
+
+/// Parse a markdown source file, together with its yaml header
+pub fn parse_headed(source: &str) -> Node {
+    markdown::to_mdast(
+        source,
+        &ParseOptions {
+            constructs: Constructs {
+                frontmatter: true, // needed to load the yaml too
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .expect("Normal markdown has no format error")
+}
+
+/// Render a markdown tree back to markdown source
+pub fn stringify(node: &Node) -> String {
+    block(node)
+}
+
+fn is_inline(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Text(_)
+            | Node::Emphasis(_)
+            | Node::Strong(_)
+            | Node::Delete(_)
+            | Node::InlineCode(_)
+            | Node::InlineMath(_)
+            | Node::Break(_)
+            | Node::Link(_)
+            | Node::LinkReference(_)
+            | Node::Image(_)
+            | Node::ImageReference(_)
+            | Node::FootnoteReference(_)
+    )
+}
+
+/// Render a list of blocks, separated by `sep`
+///
+/// Runs of inline nodes are rendered as a single line, as happens in the
+/// content of a heading
+fn blocks(children: &[Node], sep: &str) -> String {
+    let mut rendered = vec![];
+    let mut run = String::new();
+    for node in children {
+        if is_inline(node) {
+            run.push_str(&inline(node));
+        } else {
+            if !run.is_empty() {
+                rendered.push(escape_line_start(&std::mem::take(&mut run)));
+            }
+            rendered.push(block(node));
+        }
+    }
+    if !run.is_empty() {
+        rendered.push(escape_line_start(&run));
+    }
+    rendered.join(sep)
+}
+
+fn block(node: &Node) -> String {
+    match node {
+        Node::Root(Root { children, .. }) => blocks(children, "\n\n"),
+        Node::Paragraph(Paragraph { children, .. }) => escape_line_start(&inlines(children)),
+        Node::Heading(Heading {
+            children, depth, ..
+        }) => format!("{} {}", "#".repeat(*depth as usize), inlines(children)),
+        Node::ThematicBreak(_) => "***".to_owned(),
+        Node::BlockQuote(BlockQuote { children, .. }) => blocks(children, "\n\n")
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    ">".to_owned()
+                } else {
+                    format!("> {line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Node::List(List {
+            children,
+            ordered,
+            start,
+            spread,
+            ..
+        }) => children
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if *ordered {
+                    format!("{}. ", start.unwrap_or(1) as usize + i)
+                } else {
+                    "- ".to_owned()
+                };
+                indent(&marker, &block(item))
+            })
+            .collect::<Vec<_>>()
+            .join(if *spread { "\n\n" } else { "\n" }),
+        Node::ListItem(ListItem {
+            children, spread, ..
+        }) => blocks(children, if *spread { "\n\n" } else { "\n" }),
+        Node::Code(Code {
+            value, lang, meta, ..
+        }) => {
+            let mut fence = "```".to_owned();
+            while value.contains(&fence) {
+                fence.push('`')
+            }
+            let info = [lang.as_deref(), meta.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{fence}{info}\n{value}\n{fence}")
+        }
+        Node::Html(Html { value, .. }) => value.clone(),
+        Node::Yaml(Yaml { value, .. }) => format!("---\n{}\n---", value.trim_end()),
+        Node::Definition(Definition {
+            url,
+            title,
+            identifier,
+            label,
+            ..
+        }) => format!(
+            "[{}]: {}{}",
+            label.as_ref().unwrap_or(identifier),
+            destination(url),
+            link_title(title)
+        ),
+        node if is_inline(node) => inline(node),
+        node => match node.children() {
+            Some(children) => blocks(children, "\n\n"),
+            None => node.to_string(),
+        },
+    }
+}
+
+fn inlines(children: &[Node]) -> String {
+    children.iter().map(inline).collect()
+}
+
+fn inline(node: &Node) -> String {
+    match node {
+        Node::Root(Root { children, .. }) | Node::Paragraph(Paragraph { children, .. }) => {
+            inlines(children)
+        }
+        Node::Text(Text { value, .. }) => escape(value),
+        Node::Emphasis(Emphasis { children, .. }) => format!("_{}_", inlines(children)),
+        Node::Strong(Strong { children, .. }) => format!("**{}**", inlines(children)),
+        Node::Delete(Delete { children, .. }) => format!("~~{}~~", inlines(children)),
+        Node::InlineCode(InlineCode { value, .. }) => {
+            let mut ticks = "`".to_owned();
+            while value.contains(&ticks) {
+                ticks.push('`')
+            }
+            if value.starts_with('`') || value.ends_with('`') {
+                format!("{ticks} {value} {ticks}")
+            } else {
+                format!("{ticks}{value}{ticks}")
+            }
+        }
+        Node::Break(_) => "\\\n".to_owned(),
+        Node::Link(Link {
+            children,
+            url,
+            title,
+            ..
+        }) => format!(
+            "[{}]({}{})",
+            inlines(children),
+            destination(url),
+            link_title(title)
+        ),
+        Node::Image(Image {
+            alt, url, title, ..
+        }) => format!(
+            "![{}]({}{})",
+            escape(alt),
+            destination(url),
+            link_title(title)
+        ),
+        Node::LinkReference(LinkReference {
+            children,
+            reference_kind,
+            identifier,
+            label,
+            ..
+        }) => format!(
+            "[{}]{}",
+            inlines(children),
+            reference(reference_kind, label.as_ref().unwrap_or(identifier))
+        ),
+        Node::ImageReference(ImageReference {
+            alt,
+            reference_kind,
+            identifier,
+            label,
+            ..
+        }) => format!(
+            "![{}]{}",
+            escape(alt),
+            reference(reference_kind, label.as_ref().unwrap_or(identifier))
+        ),
+        Node::Html(Html { value, .. }) => value.clone(),
+        node => node.to_string(),
+    }
+}
+
+fn reference(kind: &ReferenceKind, label: &str) -> String {
+    match kind {
+        ReferenceKind::Shortcut => String::new(),
+        ReferenceKind::Collapsed => "[]".to_owned(),
+        ReferenceKind::Full => format!("[{label}]"),
+    }
+}
+
+fn destination(url: &str) -> String {
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        format!("<{url}>")
+    } else {
+        url.to_owned()
+    }
+}
+
+fn link_title(title: &Option<String>) -> String {
+    match title {
+        Some(title) => format!(" \"{}\"", title.replace('"', "\\\"")),
+        None => String::new(),
+    }
+}
+
+/// Prefix the first line with `marker`, and indent the others to match
+fn indent(marker: &str, content: &str) -> String {
+    let pad = " ".repeat(marker.len());
+    let mut lines = content.lines();
+    let mut out = format!("{marker}{}", lines.next().unwrap_or_default());
+    for line in lines {
+        out.push('\n');
+        if !line.is_empty() {
+            out.push_str(&pad);
+            out.push_str(line);
+        }
+    }
+    out.trim_end().to_owned()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '*' | '_' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Escape text that would be read as the start of a block
+fn escape_line_start(line: &str) -> String {
+    if regex_is_match!(r"^(#{1,6}(\s|$)|[>+\-=]|\d+[.)])", line) {
+        format!("\\{line}")
+    } else {
+        line.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md(source: &str) -> Markdown {
+        serde_json::from_value(source.into()).unwrap()
+    }
+
+    #[test]
+    fn serialized_as_markdown_source() {
+        let text = md("Cuocere il _pomodoro_ nella [pentola](pentola.md).\n\n- uno\n- due");
+        assert_eq!(
+            serde_json::to_value(&text).unwrap(),
+            "Cuocere il _pomodoro_ nella [pentola](pentola.md).\n\n- uno\n- due"
+        );
+        assert_eq!(
+            serde_yaml::to_string(&text).unwrap(),
+            "|-\n  Cuocere il _pomodoro_ nella [pentola](pentola.md).\n\n  - uno\n  - due\n"
+        );
+    }
+
+    #[test]
+    fn serialized_source_loads_back_the_same() {
+        let text = md("Un **sugo** con `olio` e ![basilico](basilico.jpg \"foglie\")");
+        let source = serde_json::to_value(&text).unwrap();
+        let loaded: Markdown = serde_json::from_value(source.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), source);
+    }
+}
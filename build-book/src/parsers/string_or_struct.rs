@@ -4,6 +4,11 @@ use std::{
     str::FromStr,
 };
 
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
 use serde::{
     de::{self, MapAccess, Visitor},
     Deserialize, Deserializer,
@@ -54,3 +59,18 @@ where
 
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
+
+/// Schema of a value read with [`string_or_struct`]: either a string, described by
+/// `description`, or the map form of `T`
+pub fn schema<T: JsonSchema>(gen: &mut SchemaGenerator, description: &str) -> Schema {
+    let mut string = gen.subschema_for::<String>().into_object();
+    string.metadata().description = Some(description.to_owned());
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![string.into(), gen.subschema_for::<T>()]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
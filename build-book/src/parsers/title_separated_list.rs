@@ -1,12 +1,14 @@
 //! Markdown file containing a list of heading separated paragraph
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use markdown::mdast::{Heading, Node, Root};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{DisplayMd, FromMd};
+use crate::diagnostic::Located;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct TitleSeparatedList<Title, Content> {
     pub items: Vec<Item<Title, Content>>,
 }
@@ -34,13 +36,12 @@ where
                         }),
                     })
                 }
-                _ => items
-                    .last_mut()
-                    .expect("File should begin with a heading")
-                    .content
-                    .children_mut()
-                    .unwrap()
-                    .push(node),
+                _ => {
+                    let Some(last) = items.last_mut() else {
+                        bail!(Located::at(&node, "File should begin with a heading"))
+                    };
+                    last.content.children_mut().unwrap().push(node)
+                }
             }
         }
         Ok(Self {
@@ -85,7 +86,7 @@ where
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Item<Title, Content> {
     pub title: Title,
     pub content: Content,
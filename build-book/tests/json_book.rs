@@ -0,0 +1,64 @@
+//! The json frontend writes a book described by its schema, that loads back the same
+
+#![cfg(feature = "frontend-json")]
+
+use std::{
+    env::temp_dir,
+    fs::{create_dir_all, read_to_string, remove_dir_all},
+    path::Path,
+    process,
+};
+
+use build_book::{frontends::json, Book};
+use serde_json::Value;
+
+/// Check that the keys of `value` are the properties of `schema`, and that the
+/// required ones are there
+fn check_keys(value: &Value, schema: &Value, what: &str) {
+    let properties = schema["properties"]
+        .as_object()
+        .unwrap_or_else(|| panic!("{what} should have properties"));
+    let object = value
+        .as_object()
+        .unwrap_or_else(|| panic!("{what} should be an object"));
+    for key in object.keys() {
+        assert!(
+            properties.contains_key(key),
+            "{what} has the unknown key `{key}`"
+        );
+    }
+    for key in schema["required"].as_array().into_iter().flatten() {
+        let key = key.as_str().unwrap();
+        assert!(object.contains_key(key), "{what} misses the key `{key}`");
+    }
+}
+
+#[test]
+fn book_follows_the_schema_and_loads_back() {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("../book");
+    let output = temp_dir().join(format!("build-book-json-{}", process::id()));
+    let _ = remove_dir_all(&output);
+    create_dir_all(&output).unwrap();
+
+    let book = Book::load(&source).expect("the book should load");
+    json::emit(
+        book.clone(),
+        output.join("book.json"),
+        Some(&output.join("book.schema.json")),
+    )
+    .expect("the json book should be written");
+    let written = read_to_string(output.join("book.json")).unwrap();
+    let schema: Value =
+        serde_json::from_str(&read_to_string(output.join("book.schema.json")).unwrap()).unwrap();
+    let _ = remove_dir_all(&output);
+
+    let value: Value = serde_json::from_str(&written).unwrap();
+    check_keys(&value, &schema, "the book");
+    let recipe = &schema["definitions"]["Recipe"];
+    for (name, value) in value["recipes"].as_object().unwrap() {
+        check_keys(value, recipe, &format!("the recipe `{name}`"));
+    }
+
+    let loaded: Book = serde_json::from_str(&written).expect("the json book should load");
+    assert!(book.equivalent(&loaded));
+}
@@ -0,0 +1,21 @@
+//! The markdown frontend writes a book that loads back the same
+
+#![cfg(feature = "frontend-markdown")]
+
+use std::{env::temp_dir, fs::remove_dir_all, path::Path, process};
+
+use build_book::{frontends::markdown, Book};
+
+#[test]
+fn book_loads_back_the_same() {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("../book");
+    let output = temp_dir().join(format!("build-book-round-trip-{}", process::id()));
+    let _ = remove_dir_all(&output);
+
+    let book = Book::load(&source).expect("the book should load");
+    markdown::emit(book.clone(), &output).expect("the book should be written");
+    let written = Book::load(&output).expect("the written book should load");
+    let _ = remove_dir_all(&output);
+
+    assert!(book.equivalent(&written));
+}
@@ -0,0 +1,33 @@
+//! A book imported from the yaml frontend output loads back the same
+
+#![cfg(feature = "frontend-yaml")]
+
+use std::{
+    env::temp_dir,
+    fs::{create_dir_all, remove_dir_all},
+    path::Path,
+    process,
+};
+
+use build_book::{frontends::yaml, Book};
+
+#[test]
+fn imported_book_loads_back_the_same() {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("../book");
+    let output = temp_dir().join(format!("build-book-yaml-round-trip-{}", process::id()));
+    let _ = remove_dir_all(&output);
+    create_dir_all(&output).unwrap();
+    let yaml = output.join("book.yml");
+    let sources = output.join("book");
+
+    let book = Book::load(&source).expect("the book should load");
+    yaml::emit(book.clone(), &yaml).expect("the yaml book should be written");
+    let imported = Book::load_yaml(&yaml).expect("the yaml book should load");
+    imported
+        .regenerate(&sources)
+        .expect("the sources should be written");
+    let written = Book::load(&sources).expect("the written book should load");
+    let _ = remove_dir_all(&output);
+
+    assert!(book.equivalent(&written));
+}